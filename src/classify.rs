@@ -0,0 +1,228 @@
+use crate::config::{Category, Config};
+use crate::error::KondoError;
+use crate::rules::Rule;
+use crate::sniff::{is_same_format, sniff_file_type};
+use std::path::{Path, PathBuf};
+
+/// Decides which category a file belongs to
+pub struct Classifier<'a> {
+    rules: &'a [Rule],
+    categories: &'a [Category],
+    default: Option<&'a Category>,
+    sniff: bool,
+}
+
+pub struct Classification<'a> {
+    /// `None` when the file matches no category and should be left alone
+    pub category: Option<&'a Category>,
+    /// Set when sniffing found content that disagrees with the extension
+    pub warning: Option<String>,
+}
+
+impl<'a> Classifier<'a> {
+    pub fn new(config: &'a Config) -> Classifier<'a> {
+        Classifier {
+            rules: &config.rules,
+            categories: &config.categories,
+            default: config.default.as_ref(),
+            sniff: config.options.sniff,
+        }
+    }
+
+    /// The first matching rule decides, or else the category of the file's
+    /// type, or else the default
+    pub fn classify(&self, file_path: &Path) -> std::io::Result<Classification<'a>> {
+        for rule in self.rules {
+            if rule.conditions.matches(file_path)? {
+                return Ok(Classification {
+                    category: Some(&rule.category),
+                    warning: None,
+                });
+            }
+        }
+
+        let mut classification = if self.sniff {
+            self.sniff_category(file_path)?
+        } else {
+            Classification {
+                category: self.find_category(file_path),
+                warning: None,
+            }
+        };
+
+        classification.category = classification.category.or(self.default);
+        Ok(classification)
+    }
+
+    // Find the category with the most specific extension matching the file name
+    fn find_category(&self, file_path: &Path) -> Option<&'a Category> {
+        self.categories
+            .iter()
+            .rev()
+            .filter_map(|c| matching_extension(file_path, &c.extensions).map(|ext| (ext.len(), c)))
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, category)| category)
+    }
+
+    // Pick the category from the file's content when its extension maps to none,
+    // warning when the two disagree. A known extension keeps its category.
+    fn sniff_category(&self, file_path: &Path) -> std::io::Result<Classification<'a>> {
+        let by_name = self.find_category(file_path);
+
+        let Some(detected) = sniff_file_type(file_path)? else {
+            return Ok(Classification { category: by_name, warning: None });
+        };
+
+        let warning = match get_extension(file_path) {
+            Some(extension) if is_same_format(&extension, detected) => {
+                return Ok(Classification { category: by_name, warning: None });
+            }
+            Some(extension) => Some(format!(
+                "{} has extension .{} but looks like a {} file",
+                file_path.display(),
+                extension,
+                detected
+            )),
+            None => None,
+        };
+
+        let category = by_name.or_else(|| {
+            self.categories
+                .iter()
+                .find(|c| c.extensions.iter().any(|ext| is_same_format(ext, detected)))
+        });
+
+        Ok(Classification { category, warning })
+    }
+}
+
+/// Classify every file, with its category or `None` when it is to be left
+/// alone. Warnings are added to `warnings`, and files that cannot be read to
+/// `failures`.
+pub fn classify_files<'a>(
+    config: &'a Config,
+    paths: Vec<PathBuf>,
+    warnings: &mut Vec<String>,
+    failures: &mut Vec<KondoError>,
+) -> Vec<(PathBuf, Option<&'a Category>)> {
+    let classifier = Classifier::new(config);
+    let mut classified = Vec::new();
+    for path in paths {
+        match classifier.classify(&path) {
+            Ok(classification) => {
+                warnings.extend(classification.warning);
+                classified.push((path, classification.category));
+            }
+            Err(e) => failures.push(KondoError::io(&path, e)),
+        }
+    }
+    classified
+}
+
+pub(crate) fn get_extension(file_path: &Path) -> Option<String> {
+    file_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|s| s.to_lowercase())
+}
+
+// The longest of `extensions` the file name ends with, so that
+// `backup.tar.gz` matches `tar.gz` rather than just `gz`
+pub(crate) fn matching_extension<'a>(file_path: &Path, extensions: &'a [String]) -> Option<&'a str> {
+    let name = file_path.file_name()?.to_string_lossy().to_lowercase();
+
+    extensions
+        .iter()
+        .filter(|ext| name.len() > ext.len() + 1 && name.ends_with(ext.as_str()))
+        .filter(|ext| name[..name.len() - ext.len()].ends_with('.'))
+        .max_by_key(|ext| ext.len())
+        .map(String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const CONFIG: &str = r#"
+[options]
+sniff = true
+
+[categories.pictures]
+extensions = ["jpg", "bmp"]
+destination = "Pictures"
+
+[categories.documents]
+extensions = ["txt"]
+destination = "Documents"
+"#;
+
+    fn category_of(classification: &Classification) -> Option<String> {
+        classification.category.map(|c| c.name.clone())
+    }
+
+    #[test]
+    fn a_known_extension_keeps_its_category_when_the_content_disagrees() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::parse(CONFIG, dir.path()).unwrap();
+        let path = dir.path().join("car.txt");
+        fs::write(&path, b"\xFF\xD8\xFF\xE0 not really text").unwrap();
+
+        let classification = Classifier::new(&config).classify(&path).unwrap();
+        assert_eq!(category_of(&classification).as_deref(), Some("documents"));
+        assert!(classification.warning.unwrap().contains("looks like a jpg file"));
+    }
+
+    #[test]
+    fn warnings_and_failures_are_returned_with_the_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::parse(CONFIG, dir.path()).unwrap();
+        let (car, notes, missing) = (dir.path().join("car.txt"), dir.path().join("notes"), dir.path().join("gone.txt"));
+        fs::write(&car, b"\xFF\xD8\xFF\xE0 not really text").unwrap();
+        fs::write(&notes, "plain text").unwrap();
+
+        let (mut warnings, mut failures) = (Vec::new(), Vec::new());
+        let paths = vec![car.clone(), notes.clone(), missing];
+        let classified = classify_files(&config, paths, &mut warnings, &mut failures);
+
+        let names: Vec<_> = classified.iter().map(|(path, c)| (path.clone(), c.map(|c| c.name.as_str()))).collect();
+        assert_eq!(names, [(car, Some("documents")), (notes, None)]);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("looks like a jpg file"), "{}", warnings[0]);
+        assert_eq!(failures.len(), 1);
+    }
+
+    #[test]
+    fn text_starting_with_bm_is_not_a_bitmap() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::parse(CONFIG, dir.path()).unwrap();
+        let path = dir.path().join("car.txt");
+        fs::write(&path, "BMW service notes, 2024").unwrap();
+
+        let classification = Classifier::new(&config).classify(&path).unwrap();
+        assert_eq!(category_of(&classification).as_deref(), Some("documents"));
+        assert!(classification.warning.is_none());
+    }
+
+    #[test]
+    fn content_places_files_without_a_known_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::parse(CONFIG, dir.path()).unwrap();
+        let path = dir.path().join("IMG_0001");
+        fs::write(&path, b"\xFF\xD8\xFF\xE0\x00\x10JFIF").unwrap();
+
+        let classification = Classifier::new(&config).classify(&path).unwrap();
+        assert_eq!(category_of(&classification).as_deref(), Some("pictures"));
+        assert!(classification.warning.is_none());
+    }
+
+    #[test]
+    fn the_longest_extension_matches() {
+        let extensions = vec!["gz".to_string(), "tar.gz".to_string()];
+        assert_eq!(matching_extension(Path::new("b.TAR.GZ"), &extensions), Some("tar.gz"));
+        assert_eq!(matching_extension(Path::new("b.gz"), &extensions), Some("gz"));
+        assert_eq!(matching_extension(Path::new("tar.gz"), &extensions), Some("gz"));
+        assert_eq!(matching_extension(Path::new(".gz"), &extensions), None);
+        assert_eq!(matching_extension(Path::new("bgz"), &extensions), None);
+    }
+}
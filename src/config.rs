@@ -0,0 +1,819 @@
+use crate::error::KondoError;
+use crate::parallel::{default_jobs, MAX_JOBS};
+use crate::plan::{ConflictPolicy, DuplicateAction};
+use crate::rules::{parse_age, parse_size, Conditions, Rule};
+use crate::template::{check_template, DateSource};
+use crate::transfer::TransferMode;
+use globset::{Glob, GlobBuilder};
+use ignore::gitignore::GitignoreBuilder;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml::Spanned;
+
+/// A category of files, as declared under `[categories.<name>]` in the config
+#[derive(Clone, Debug)]
+pub struct Category {
+    pub name: String,
+    /// Lowercase extensions without the leading dot, e.g. `jpg` or `tar.gz`
+    pub extensions: Vec<String>,
+    /// May contain placeholders such as `{year}`, expanded per file
+    pub destination: PathBuf,
+    /// Template for renaming files, without the extension, e.g. `{track:02} - {title}`
+    pub rename: Option<String>,
+    /// How files get to the destination, instead of the global `mode`
+    pub mode: Option<TransferMode>,
+}
+
+/// The `[options]` table of the config
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub on_conflict: ConflictPolicy,
+    /// How files get to their destination, unless their category says otherwise
+    pub mode: TransferMode,
+    pub exclude: Vec<String>,
+    /// Patterns of files to leave alone, in `.gitignore` syntax
+    pub ignore: Vec<String>,
+    pub sniff: bool,
+    pub date_from: DateSource,
+    /// Date photos without an EXIF capture date by `date_from`, rather than
+    /// putting them in the `undated` folder
+    pub exif_fallback: bool,
+    /// Folder taking the place of the date in a destination for undated files
+    pub undated: String,
+    /// What to do with files identical to one at their destination, or to
+    /// another file of the run. `None` leaves duplicates to `on_conflict`.
+    pub on_duplicate: Option<DuplicateAction>,
+    /// Where `on_duplicate = "move"` puts duplicates
+    pub duplicates: PathBuf,
+    /// How many files are classified and transferred at once
+    pub jobs: usize,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            on_conflict: ConflictPolicy::Rename,
+            mode: TransferMode::Move,
+            exclude: Vec::new(),
+            ignore: Vec::new(),
+            sniff: false,
+            date_from: DateSource::Modified,
+            exif_fallback: true,
+            undated: "Undated".to_string(),
+            on_duplicate: None,
+            duplicates: PathBuf::from("Duplicates"),
+            jobs: default_jobs(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub categories: Vec<Category>,
+    /// Checked in order before the categories, the first matching rule wins
+    pub rules: Vec<Rule>,
+    /// Where files matching no rule or category go, if anywhere
+    pub default: Option<Category>,
+    pub options: Options,
+    /// In the order of their names
+    pub profiles: Vec<Profile>,
+    /// Unknown keys found while loading, which are otherwise ignored
+    pub warnings: Vec<String>,
+}
+
+/// A set of source directories organized their own way, as declared under
+/// `[profiles.<name>]` in the config
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub name: String,
+    /// Directories to organize, in order
+    pub sources: Vec<PathBuf>,
+    /// The categories, rules, default and options of the profile. Those of
+    /// the top level apply too, unless the profile has its own of the same
+    /// name, and each option the profile sets replaces the top-level one.
+    pub config: Config,
+}
+
+// The config file as written, before paths are expanded
+#[derive(Deserialize)]
+struct RawConfig {
+    #[serde(default)]
+    categories: BTreeMap<String, RawCategory>,
+    #[serde(default)]
+    rules: Vec<RawRule>,
+    default: Option<RawDefault>,
+    #[serde(default)]
+    options: RawOptions,
+    #[serde(default)]
+    profiles: BTreeMap<String, RawProfile>,
+    // The format of the first versions: a destination for each of a fixed
+    // set of categories
+    directories: Option<BTreeMap<String, Spanned<String>>>,
+}
+
+#[derive(Deserialize)]
+struct RawProfile {
+    sources: Spanned<Vec<Spanned<String>>>,
+    #[serde(default)]
+    categories: BTreeMap<String, RawCategory>,
+    #[serde(default)]
+    rules: Vec<RawRule>,
+    default: Option<RawDefault>,
+    #[serde(default)]
+    options: RawOptions,
+}
+
+#[derive(Deserialize)]
+struct RawCategory {
+    extensions: Vec<String>,
+    destination: Spanned<String>,
+    rename: Option<Spanned<String>>,
+    mode: Option<TransferMode>,
+}
+
+#[derive(Deserialize)]
+struct RawRule {
+    name: Option<String>,
+    glob: Option<Spanned<String>>,
+    regex: Option<Spanned<String>>,
+    #[serde(default)]
+    extensions: Vec<String>,
+    min_size: Option<Spanned<String>>,
+    max_size: Option<Spanned<String>>,
+    min_age: Option<Spanned<String>>,
+    max_age: Option<Spanned<String>>,
+    #[serde(default)]
+    age_from: DateSource,
+    parent: Option<Spanned<String>>,
+    mime: Option<Spanned<String>>,
+    destination: Spanned<String>,
+    rename: Option<Spanned<String>>,
+    mode: Option<TransferMode>,
+}
+
+#[derive(Deserialize)]
+struct RawDefault {
+    destination: Spanned<String>,
+    rename: Option<Spanned<String>>,
+    mode: Option<TransferMode>,
+}
+
+// Every option is optional, so that a profile only replaces those it sets
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+struct RawOptions {
+    on_conflict: Option<ConflictPolicy>,
+    mode: Option<TransferMode>,
+    exclude: Option<Vec<Spanned<String>>>,
+    ignore: Option<Vec<Spanned<String>>>,
+    sniff: Option<bool>,
+    date_from: Option<DateSource>,
+    exif_fallback: Option<bool>,
+    undated: Option<String>,
+    on_duplicate: Option<DuplicateAction>,
+    duplicates: Option<Spanned<String>>,
+    jobs: Option<Spanned<usize>>,
+}
+
+impl RawOptions {
+    // These options, with those left out taken from `base`
+    fn or(self, base: &RawOptions) -> RawOptions {
+        RawOptions {
+            on_conflict: self.on_conflict.or(base.on_conflict),
+            mode: self.mode.or(base.mode),
+            exclude: self.exclude.or_else(|| base.exclude.clone()),
+            ignore: self.ignore.or_else(|| base.ignore.clone()),
+            sniff: self.sniff.or(base.sniff),
+            date_from: self.date_from.or(base.date_from),
+            exif_fallback: self.exif_fallback.or(base.exif_fallback),
+            undated: self.undated.or_else(|| base.undated.clone()),
+            on_duplicate: self.on_duplicate.or(base.on_duplicate),
+            duplicates: self.duplicates.or_else(|| base.duplicates.clone()),
+            jobs: self.jobs.or_else(|| base.jobs.clone()),
+        }
+    }
+}
+
+impl Config {
+    /// Read and parse a config file. Relative destinations are resolved
+    /// against the directory holding it.
+    pub fn load(path: &Path) -> Result<Config, KondoError> {
+        let config_error = |message: String| KondoError::ConfigFile {
+            path: path.to_path_buf(),
+            message,
+        };
+
+        let text = fs::read_to_string(path).map_err(|e| config_error(e.to_string()))?;
+        let base_dir = std::path::absolute(path)
+            .map_err(|e| KondoError::io(path, e))?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        Config::parse(&text, &base_dir).map_err(config_error)
+    }
+
+    /// Parse the text of a config file. Errors point at the offending line
+    /// and column.
+    pub fn parse(text: &str, base_dir: &Path) -> Result<Config, String> {
+        let mut warnings = Vec::new();
+        let raw: RawConfig = serde_ignored::deserialize(toml::Deserializer::new(text), |key| {
+            warnings.push(format!("unknown key '{}' is ignored", key));
+        })
+        .map_err(|e| e.to_string())?;
+
+        let mut raw_categories = raw.categories;
+        if let Some(directories) = raw.directories {
+            if raw_categories.is_empty() {
+                raw_categories = legacy_categories(text, directories)?;
+                warnings.push("'directories' is the old format, read as categories with the usual extensions".into());
+            } else {
+                warnings.push("'directories' is ignored, as 'categories' replaces it".to_string());
+            }
+        }
+
+        let categories = parse_categories(text, base_dir, "", raw_categories)?;
+        let rules = parse_rules(text, base_dir, "", raw.rules)?;
+        let default = parse_default(text, base_dir, "", raw.default)?;
+        let options = parse_options(text, base_dir, "", raw.options.clone())?;
+
+        let mut profiles = Vec::new();
+        for (name, profile) in raw.profiles {
+            let prefix = format!("profiles.{}.", name);
+
+            let key = format!("{}sources", prefix);
+            if profile.sources.get_ref().is_empty() {
+                let message = format!("'{}' must list at least one directory", key);
+                return Err(error_at(text, profile.sources.span(), message));
+            }
+            let mut sources = Vec::new();
+            for source in profile.sources.into_inner() {
+                let span = source.span();
+                sources.push(expand_path(source.get_ref(), base_dir).map_err(|var| {
+                    error_at(text, span, format!("undefined variable '{}' in '{}'", var, key))
+                })?);
+            }
+
+            // The profile's own categories replace those of the same name
+            let mut own_categories = parse_categories(text, base_dir, &prefix, profile.categories)?;
+            let mut profile_categories: Vec<Category> = categories
+                .iter()
+                .filter(|category| !own_categories.iter().any(|own| own.name == category.name))
+                .cloned()
+                .collect();
+            profile_categories.append(&mut own_categories);
+            profile_categories.sort_by(|a, b| a.name.cmp(&b.name));
+
+            // and its rules are checked before the top-level ones
+            let mut profile_rules = parse_rules(text, base_dir, &prefix, profile.rules)?;
+            profile_rules.extend(rules.iter().cloned());
+
+            let config = Config {
+                categories: profile_categories,
+                rules: profile_rules,
+                default: parse_default(text, base_dir, &prefix, profile.default)?.or_else(|| default.clone()),
+                options: parse_options(text, base_dir, &prefix, profile.options.or(&raw.options))?,
+                ..Config::default()
+            };
+            profiles.push(Profile { name, sources, config });
+        }
+
+        Ok(Config {
+            categories,
+            rules,
+            default,
+            options,
+            profiles,
+            warnings,
+        })
+    }
+
+    /// The profile with this name
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// Every place files can go to: the rules, the categories and the default
+    pub fn targets(&self) -> impl Iterator<Item = &Category> {
+        self.rules
+            .iter()
+            .map(|rule| &rule.category)
+            .chain(&self.categories)
+            .chain(&self.default)
+    }
+}
+
+// Extensions of the categories `[directories]` used to have a destination for
+pub(crate) const LEGACY_CATEGORIES: &[(&str, &[&str])] = &[
+    ("images", &["jpg", "jpeg", "png", "gif", "bmp", "tiff", "webp"]),
+    ("documents", &["pdf", "doc", "docx", "txt", "rtf", "odt", "xls", "xlsx", "ppt", "pptx"]),
+    ("audio", &["mp3", "wav", "ogg", "flac", "aac", "wma"]),
+];
+
+// The categories `[directories]` stands for, with the extensions they had
+fn legacy_categories(
+    text: &str,
+    directories: BTreeMap<String, Spanned<String>>,
+) -> Result<BTreeMap<String, RawCategory>, String> {
+    let mut categories = BTreeMap::new();
+    for (name, destination) in directories {
+        let Some((_, extensions)) = LEGACY_CATEGORIES.iter().find(|(legacy, _)| *legacy == name) else {
+            let message = format!(
+                "'directories.{}' is not one of images, documents or audio; list its extensions in \
+                 [categories.{}] instead, as `extensions = [...]` and `destination = \"...\"`",
+                name, name
+            );
+            return Err(error_at(text, destination.span(), message));
+        };
+        let category = RawCategory {
+            extensions: extensions.iter().map(|ext| ext.to_string()).collect(),
+            destination,
+            rename: None,
+            mode: None,
+        };
+        categories.insert(name, category);
+    }
+    Ok(categories)
+}
+
+// The categories under `<prefix>categories`, in the order of their names
+fn parse_categories(
+    text: &str,
+    base_dir: &Path,
+    prefix: &str,
+    raw: BTreeMap<String, RawCategory>,
+) -> Result<Vec<Category>, String> {
+    let mut categories = Vec::new();
+    for (name, category) in raw {
+        let key = format!("{}categories.{}", prefix, name);
+        categories.push(Category {
+            destination: parse_destination(text, base_dir, category.destination, &key)?,
+            rename: parse_rename(text, category.rename, &key)?,
+            mode: category.mode,
+            name,
+            extensions: lowercase_extensions(&category.extensions),
+        });
+    }
+    Ok(categories)
+}
+
+fn parse_rules(text: &str, base_dir: &Path, prefix: &str, raw: Vec<RawRule>) -> Result<Vec<Rule>, String> {
+    let mut rules = Vec::new();
+    for (index, rule) in raw.into_iter().enumerate() {
+        let key = format!("{}rules[{}]", prefix, index);
+        rules.push(parse_rule(text, base_dir, rule, &key)?);
+    }
+    Ok(rules)
+}
+
+fn parse_default(
+    text: &str,
+    base_dir: &Path,
+    prefix: &str,
+    raw: Option<RawDefault>,
+) -> Result<Option<Category>, String> {
+    let Some(default) = raw else {
+        return Ok(None);
+    };
+
+    let key = format!("{}default", prefix);
+    Ok(Some(Category {
+        name: "default".to_string(),
+        extensions: Vec::new(),
+        destination: parse_destination(text, base_dir, default.destination, &key)?,
+        rename: parse_rename(text, default.rename, &key)?,
+        mode: default.mode,
+    }))
+}
+
+fn parse_options(text: &str, base_dir: &Path, prefix: &str, raw: RawOptions) -> Result<Options, String> {
+    let mut exclude = Vec::new();
+    for pattern in raw.exclude.unwrap_or_default() {
+        let span = pattern.span();
+        let pattern = pattern.into_inner();
+        Glob::new(&pattern).map_err(|e| {
+            let message = format!("invalid pattern '{}' in '{}options.exclude': {}", pattern, prefix, e.kind());
+            error_at(text, span, message)
+        })?;
+        exclude.push(pattern);
+    }
+
+    let mut ignore = Vec::new();
+    for pattern in raw.ignore.unwrap_or_default() {
+        let span = pattern.span();
+        let pattern = pattern.into_inner();
+        let mut builder = GitignoreBuilder::new(base_dir);
+        builder.add_line(None, &pattern).and_then(|builder| builder.build()).map_err(|e| {
+            error_at(text, span, format!("invalid pattern '{}' in '{}options.ignore': {}", pattern, prefix, e))
+        })?;
+        ignore.push(pattern);
+    }
+
+    let jobs = match raw.jobs {
+        Some(jobs) if !(1..=MAX_JOBS).contains(jobs.get_ref()) => {
+            let message = format!("'{}options.jobs' must be from 1 to {}", prefix, MAX_JOBS);
+            return Err(error_at(text, jobs.span(), message));
+        }
+        Some(jobs) => jobs.into_inner(),
+        None => default_jobs(),
+    };
+
+    // `~/Duplicates` by default, or next to the config file without a home
+    let duplicates = match raw.duplicates {
+        Some(duplicates) => {
+            let span = duplicates.span();
+            expand_path(duplicates.get_ref(), base_dir).map_err(|var| {
+                error_at(text, span, format!("undefined variable '{}' in '{}options.duplicates'", var, prefix))
+            })?
+        }
+        None => expand_path("~/Duplicates", base_dir).unwrap_or_else(|_| base_dir.join("Duplicates")),
+    };
+
+    Ok(Options {
+        on_conflict: raw.on_conflict.unwrap_or(ConflictPolicy::Rename),
+        mode: raw.mode.unwrap_or_default(),
+        exclude,
+        ignore,
+        sniff: raw.sniff.unwrap_or(false),
+        date_from: raw.date_from.unwrap_or_default(),
+        exif_fallback: raw.exif_fallback.unwrap_or(true),
+        undated: raw.undated.unwrap_or_else(|| "Undated".to_string()),
+        on_duplicate: raw.on_duplicate,
+        duplicates,
+        jobs,
+    })
+}
+
+// An error found after deserializing, pointing back into the text
+fn error_at(text: &str, span: Range<usize>, message: String) -> String {
+    let (line, column) = line_column(text, span.start);
+    format!("{} at line {}, column {}", message, line, column)
+}
+
+fn lowercase_extensions(extensions: &[String]) -> Vec<String> {
+    extensions
+        .iter()
+        .map(|ext| ext.trim_start_matches('.').to_lowercase())
+        .collect()
+}
+
+// Expand and check the destination of the category, rule or default at `key`
+fn parse_destination(text: &str, base_dir: &Path, raw: Spanned<String>, key: &str) -> Result<PathBuf, String> {
+    let span = raw.span();
+    let key = format!("{}.destination", key);
+    let destination = expand_path(raw.get_ref(), base_dir)
+        .map_err(|var| error_at(text, span.clone(), format!("undefined variable '{}' in '{}'", var, key)))?;
+    check_template(&destination.to_string_lossy())
+        .map_err(|message| error_at(text, span, format!("{} in '{}'", message, key)))?;
+    Ok(destination)
+}
+
+fn parse_rename(text: &str, raw: Option<Spanned<String>>, key: &str) -> Result<Option<String>, String> {
+    let Some(raw) = raw else {
+        return Ok(None);
+    };
+
+    let (span, rename) = (raw.span(), raw.into_inner());
+    let key = format!("{}.rename", key);
+    check_template(&rename).map_err(|message| error_at(text, span.clone(), format!("{} in '{}'", message, key)))?;
+    if rename.contains(std::path::is_separator) {
+        return Err(error_at(text, span, format!("path separator in '{}', which names a file", key)));
+    }
+    Ok(Some(rename))
+}
+
+fn parse_rule(text: &str, base_dir: &Path, raw: RawRule, key: &str) -> Result<Rule, String> {
+    // Parse an optional condition, pointing at it when it is invalid
+    fn condition<T>(
+        text: &str,
+        raw: Option<Spanned<String>>,
+        key: String,
+        parse: impl FnOnce(&str) -> Result<T, String>,
+    ) -> Result<Option<T>, String> {
+        raw.map(|raw| {
+            parse(raw.get_ref()).map_err(|message| error_at(text, raw.span(), format!("{} in '{}'", message, key)))
+        })
+        .transpose()
+    }
+    let glob = |pattern: &str| {
+        GlobBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map(|glob| glob.compile_matcher())
+            .map_err(|e| format!("invalid pattern '{}': {}", pattern, e.kind()))
+    };
+
+    let conditions = Conditions {
+        glob: condition(text, raw.glob, format!("{}.glob", key), glob)?,
+        regex: condition(text, raw.regex, format!("{}.regex", key), |pattern| {
+            Regex::new(pattern).map_err(|e| format!("invalid regex '{}': {}", pattern, e))
+        })?,
+        extensions: lowercase_extensions(&raw.extensions),
+        min_size: condition(text, raw.min_size, format!("{}.min_size", key), parse_size)?,
+        max_size: condition(text, raw.max_size, format!("{}.max_size", key), parse_size)?,
+        min_age: condition(text, raw.min_age, format!("{}.min_age", key), parse_age)?,
+        max_age: condition(text, raw.max_age, format!("{}.max_age", key), parse_age)?,
+        age_from: raw.age_from,
+        parent: condition(text, raw.parent, format!("{}.parent", key), glob)?,
+        mime: condition(text, raw.mime, format!("{}.mime", key), glob)?,
+    };
+
+    let category = Category {
+        name: raw.name.unwrap_or_else(|| key.to_string()),
+        extensions: conditions.extensions.clone(),
+        destination: parse_destination(text, base_dir, raw.destination, key)?,
+        rename: parse_rename(text, raw.rename, key)?,
+        mode: raw.mode,
+    };
+
+    Ok(Rule { conditions, category })
+}
+
+// 1-based line and column of a byte offset
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before[before.rfind('\n').map_or(0, |newline| newline + 1)..].chars().count() + 1;
+    (line, column)
+}
+
+/// Where a config file is looked for when none is given, in order:
+/// `$XDG_CONFIG_HOME/kondo/config.toml`, `~/.config/kondo/config.toml`, then
+/// `/etc/kondo/config.toml`
+pub fn config_search_paths() -> Vec<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()).map(PathBuf::from);
+    let home_config = env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"));
+
+    let mut paths = Vec::new();
+    for dir in [config_home, home_config, Some(PathBuf::from("/etc"))].into_iter().flatten() {
+        let path = dir.join("kondo").join("config.toml");
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+/// Where `kondo init` writes a config: the first of [`config_search_paths`]
+pub fn default_config_path() -> PathBuf {
+    config_search_paths().swap_remove(0)
+}
+
+/// The first of [`config_search_paths`] that exists
+pub fn find_config() -> Result<PathBuf, KondoError> {
+    let searched = config_search_paths();
+    match searched.iter().find(|path| path.is_file()) {
+        Some(path) => Ok(path.clone()),
+        None => Err(KondoError::NoConfig { searched }),
+    }
+}
+
+// Look up a variable such as `XDG_PICTURES_DIR`, falling back to the XDG
+// user-dirs.dirs file for the `XDG_*_DIR` ones most sessions don't export
+pub(crate) fn lookup_var(name: &str) -> Option<String> {
+    if let Some(value) = env::var_os(name).filter(|v| !v.is_empty()) {
+        return Some(value.to_string_lossy().into_owned());
+    }
+
+    if !(name.starts_with("XDG_") && name.ends_with("_DIR")) {
+        return None;
+    }
+
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    let user_dirs = fs::read_to_string(config_home.join("user-dirs.dirs")).ok()?;
+
+    // Lines look like `XDG_PICTURES_DIR="$HOME/Pictures"`
+    user_dirs.lines().find_map(|line| {
+        let (key, value) = line.trim().split_once('=')?;
+        if key != name {
+            return None;
+        }
+        let value = value.trim_matches('"');
+        let home = env::var("HOME").ok()?;
+        Some(value.replacen("$HOME", &home, 1))
+    })
+}
+
+/// Expand `~`, `$VAR` and `${VAR}` in a configured path and resolve it against
+/// `base_dir` when relative. Returns the name of the first undefined variable
+/// as the error.
+pub fn expand_path(raw: &str, base_dir: &Path) -> Result<PathBuf, String> {
+    let mut expanded = String::new();
+
+    let rest = if raw == "~" || raw.starts_with("~/") {
+        expanded.push_str(&lookup_var("HOME").ok_or("HOME")?);
+        &raw[1..]
+    } else {
+        raw
+    };
+
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+
+        let name: String = if chars.peek() == Some(&'{') {
+            chars.next();
+            chars.by_ref().take_while(|&c| c != '}').collect()
+        } else {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || **c == '_') {
+                name.push(c);
+                chars.next();
+            }
+            name
+        };
+
+        if name.is_empty() {
+            expanded.push('$');
+            continue;
+        }
+
+        expanded.push_str(&lookup_var(&name).ok_or(name)?);
+    }
+
+    Ok(base_dir.join(expanded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_expanded_and_resolved() {
+        let home = env::var("HOME").unwrap();
+        let base = Path::new("/base");
+
+        assert_eq!(expand_path("~", base), Ok(PathBuf::from(&home)));
+        assert_eq!(expand_path("~/Pictures", base), Ok(Path::new(&home).join("Pictures")));
+        assert_eq!(expand_path("$HOME/a", base), Ok(Path::new(&home).join("a")));
+        assert_eq!(expand_path("${HOME}b", base), Ok(PathBuf::from(format!("{}b", home))));
+        assert_eq!(expand_path("Sorted/$", base), Ok(PathBuf::from("/base/Sorted/$")));
+        assert_eq!(expand_path("~user/a", base), Ok(PathBuf::from("/base/~user/a")));
+        assert_eq!(expand_path("/abs", base), Ok(PathBuf::from("/abs")));
+        assert_eq!(expand_path("$KONDO_UNDEFINED/a", base), Err("KONDO_UNDEFINED".to_string()));
+    }
+
+    #[test]
+    fn jobs_are_limited_like_on_the_command_line() {
+        let base = Path::new("/base");
+        assert_eq!(Config::parse("[options]\njobs = 1024\n", base).unwrap().options.jobs, 1024);
+
+        for jobs in ["0", "1025", "1000000"] {
+            let error = Config::parse(&format!("[options]\njobs = {}\n", jobs), base).unwrap_err();
+            assert_eq!(error, "'options.jobs' must be from 1 to 1024 at line 2, column 8");
+        }
+        let error = Config::parse("[profiles.p]\nsources = [\"/s\"]\noptions = { jobs = 0 }\n", base).unwrap_err();
+        assert!(error.starts_with("'profiles.p.options.jobs' must be"), "{}", error);
+    }
+
+    #[test]
+    fn profiles_build_on_the_top_level() {
+        let text = r#"
+[categories.docs]
+extensions = ["txt"]
+destination = "/docs"
+
+[categories.images]
+extensions = ["jpg"]
+destination = "/images"
+
+[[rules]]
+name = "top"
+glob = "a*"
+destination = "/top"
+
+[default]
+destination = "/misc"
+
+[options]
+on_conflict = "skip"
+sniff = true
+
+[profiles.desk]
+sources = ["/desk"]
+
+[profiles.desk.categories.images]
+extensions = ["png"]
+destination = "/desk/images"
+
+[profiles.desk.categories.audio]
+extensions = ["mp3"]
+destination = "/desk/audio"
+
+[[profiles.desk.rules]]
+name = "first"
+glob = "b*"
+destination = "/first"
+
+[[profiles.desk.rules]]
+name = "second"
+glob = "c*"
+destination = "/second"
+
+[profiles.desk.options]
+on_conflict = "overwrite"
+"#;
+        let config = Config::parse(text, Path::new("/base")).unwrap();
+        let profile = &config.profile("desk").unwrap().config;
+
+        let categories: Vec<(&str, &Path)> =
+            profile.categories.iter().map(|c| (c.name.as_str(), c.destination.as_path())).collect();
+        assert_eq!(
+            categories,
+            [("audio", Path::new("/desk/audio")), ("docs", Path::new("/docs")), ("images", Path::new("/desk/images"))]
+        );
+        assert_eq!(profile.categories[2].extensions, ["png"]);
+
+        let rules: Vec<&str> = profile.rules.iter().map(|rule| rule.category.name.as_str()).collect();
+        assert_eq!(rules, ["first", "second", "top"]);
+        assert_eq!(profile.default.as_ref().unwrap().destination, Path::new("/misc"));
+
+        assert_eq!(profile.options.on_conflict, ConflictPolicy::Overwrite);
+        assert!(profile.options.sniff);
+        assert_eq!(config.options.on_conflict, ConflictPolicy::Skip);
+    }
+
+    #[test]
+    fn directories_are_read_as_categories() {
+        let text = "[directories]\nimages = \"/p\"\naudio = \"/m\"\n";
+        let config = Config::parse(text, Path::new("/base")).unwrap();
+
+        let names: Vec<&str> = config.categories.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["audio", "images"]);
+        assert_eq!(config.categories[1].destination, Path::new("/p"));
+        assert!(config.categories[1].extensions.contains(&"jpg".to_string()));
+        assert!(config.warnings[0].contains("old format"));
+    }
+
+    #[test]
+    fn unknown_directories_are_rejected() {
+        let text = "[directories]\nvideos = \"/v\"\n";
+        let error = Config::parse(text, Path::new("/base")).unwrap_err();
+        assert!(error.contains("[categories.videos]"), "{}", error);
+        assert!(error.ends_with("at line 2, column 10"), "{}", error);
+    }
+
+    #[test]
+    fn categories_take_precedence_over_directories() {
+        let text = "[directories]\nimages = \"/p\"\n\n[categories.docs]\nextensions = [\"txt\"]\ndestination = \"/d\"";
+        let config = Config::parse(text, Path::new("/base")).unwrap();
+        assert_eq!(config.categories.len(), 1);
+        assert!(config.warnings[0].contains("ignored"));
+    }
+
+    #[test]
+    fn errors_point_at_the_line_and_column() {
+        let base = Path::new("/base");
+
+        let error = Config::parse("[options\n", base).unwrap_err();
+        assert!(error.contains("at line 1, column 9"), "{}", error);
+        let error = Config::parse("[options]\nsniff = \"yes\"\n", base).unwrap_err();
+        assert!(error.contains("at line 2, column 9"), "{}", error);
+
+        let error = Config::parse("\n[[rules]]\nglob = \"[\"\ndestination = \"/x\"\n", base).unwrap_err();
+        assert!(error.starts_with("invalid pattern '['"), "{}", error);
+        assert!(error.ends_with("in 'rules[0].glob' at line 3, column 8"), "{}", error);
+
+        // Columns count characters rather than bytes
+        let text = "rules = [{ name = \"ü\", glob = \"[\", destination = \"/x\" }]\n";
+        let error = Config::parse(text, base).unwrap_err();
+        assert!(error.ends_with("at line 1, column 31"), "{}", error);
+    }
+
+    #[test]
+    fn unknown_keys_are_warned_about() {
+        let text = r#"
+[categories.a]
+extensions = ["txt"]
+destination = "/a"
+colour = 1
+
+[options]
+speed = 2
+
+[profiles.p]
+sources = ["/s"]
+extra = 1
+"#;
+        let config = Config::parse(text, Path::new("/base")).unwrap();
+        assert_eq!(
+            config.warnings,
+            [
+                "unknown key 'categories.a.colour' is ignored",
+                "unknown key 'options.speed' is ignored",
+                "unknown key 'profiles.p.extra' is ignored",
+            ]
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use crate::error::KondoError;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// BLAKE3 hash of a file's content
+pub(crate) fn hash_file(path: &Path) -> io::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(path)?)?;
+    Ok(hasher.finalize())
+}
+
+/// Whether two files have the same content, comparing sizes before hashes
+pub(crate) fn is_identical(a: &Path, b: &Path) -> io::Result<bool> {
+    Ok(fs::metadata(a)?.len() == fs::metadata(b)?.len() && hash_file(a)? == hash_file(b)?)
+}
+
+/// Whether both paths are hard links to the same file
+#[cfg(unix)]
+pub(crate) fn is_same_file(a: &Path, b: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let (a, b) = (fs::metadata(a)?, fs::metadata(b)?);
+    Ok(a.dev() == b.dev() && a.ino() == b.ino())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_same_file(_a: &Path, _b: &Path) -> io::Result<bool> {
+    Ok(false)
+}
+
+// A file whose content, read from `content`, will be found at `location`
+// once the run is over
+#[derive(Clone)]
+struct Candidate {
+    content: PathBuf,
+    location: PathBuf,
+}
+
+/// Files seen so far, grouped by size so that only files of the same size
+/// are ever hashed
+#[derive(Default)]
+pub(crate) struct DuplicateIndex {
+    by_size: HashMap<u64, Vec<Candidate>>,
+    hashes: HashMap<PathBuf, blake3::Hash>,
+    scanned_dirs: HashSet<PathBuf>,
+}
+
+impl DuplicateIndex {
+    /// Add the files directly inside `dir`, if it exists and was not added
+    /// before. Files at `is_claimed` paths are left out, as they are those of
+    /// the run, added as planned, or about to be replaced.
+    pub(crate) fn add_dir(&mut self, dir: &Path, is_claimed: impl Fn(&Path) -> bool) -> io::Result<()> {
+        if !self.scanned_dirs.insert(dir.to_path_buf()) {
+            return Ok(());
+        }
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_file() && !is_claimed(&path) {
+                self.add(&path, &path, entry.metadata()?.len());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remember that the content of `content` will end up at `location`
+    pub(crate) fn add(&mut self, content: &Path, location: &Path, size: u64) {
+        self.by_size.entry(size).or_default().push(Candidate {
+            content: content.to_path_buf(),
+            location: location.to_path_buf(),
+        });
+    }
+
+    /// Forget the files whose content was to be read from `content`
+    pub(crate) fn remove(&mut self, content: &Path) {
+        for candidates in self.by_size.values_mut() {
+            candidates.retain(|candidate| candidate.content != content);
+        }
+    }
+
+    /// Where a file with the same content as `path` is, or will be. Empty
+    /// files are never duplicates, as they are often placeholders.
+    pub(crate) fn find(&mut self, path: &Path, size: u64) -> io::Result<Option<PathBuf>> {
+        if size == 0 {
+            return Ok(None);
+        }
+        let Some(candidates) = self.by_size.get(&size).cloned() else {
+            return Ok(None);
+        };
+
+        let hash = self.hash(path)?;
+        for candidate in candidates.into_iter().filter(|c| c.content != path) {
+            // The file may have been moved to its location meanwhile, and a
+            // candidate that cannot be read at all is simply not a duplicate
+            let other = self.hash(&candidate.content).or_else(|_| self.hash(&candidate.location));
+            if other.is_ok_and(|other| other == hash) {
+                return Ok(Some(candidate.location));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn hash(&mut self, path: &Path) -> io::Result<blake3::Hash> {
+        if let Some(hash) = self.hashes.get(path) {
+            return Ok(*hash);
+        }
+
+        let hash = hash_file(path)?;
+        self.hashes.insert(path.to_path_buf(), hash);
+        Ok(hash)
+    }
+}
+
+/// Group files with identical content, for `kondo dupes`. Files are first
+/// grouped by size, and only those sharing a size are hashed. Each group
+/// keeps the order of `paths`, and groups are ordered by their first file.
+/// Files that cannot be read are added to `failures`.
+pub fn find_duplicates(paths: &[PathBuf], failures: &mut Vec<KondoError>) -> Vec<Vec<PathBuf>> {
+    // Indices into `paths`, so that everything below keeps their order
+    let mut by_size: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    for (index, path) in paths.iter().enumerate() {
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.len() > 0 => by_size.entry(metadata.len()).or_default().push(index),
+            Ok(_) => {}
+            Err(e) => failures.push(KondoError::io(path, e)),
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for same_size in by_size.into_values().filter(|files| files.len() > 1) {
+        let mut by_hash: HashMap<blake3::Hash, Vec<usize>> = HashMap::new();
+        for index in same_size {
+            match hash_file(&paths[index]) {
+                Ok(hash) => by_hash.entry(hash).or_default().push(index),
+                Err(e) => failures.push(KondoError::io(&paths[index], e)),
+            }
+        }
+        groups.extend(by_hash.into_values().filter(|group| group.len() > 1));
+    }
+
+    groups.sort();
+    groups
+        .into_iter()
+        .map(|group| group.into_iter().map(|index| paths[index].clone()).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn only_files_of_the_same_size_are_hashed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut index = DuplicateIndex::default();
+        index.add(&write(dir.path(), "a", "same"), &dir.path().join("dest/a"), 4);
+
+        // A file that cannot be read is never looked at without a candidate
+        // of its size
+        assert_eq!(index.find(&dir.path().join("missing"), 5).unwrap(), None);
+        assert!(index.find(&dir.path().join("missing"), 4).is_err());
+        assert_eq!(index.find(&write(dir.path(), "b", "diff"), 4).unwrap(), None);
+    }
+
+    #[test]
+    fn empty_files_are_never_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (write(dir.path(), "a", ""), write(dir.path(), "b", ""));
+        let mut index = DuplicateIndex::default();
+        index.add(&a, &a, 0);
+
+        assert_eq!(index.find(&b, 0).unwrap(), None);
+        assert!(find_duplicates(&[a, b], &mut Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn duplicates_within_the_run_are_found_at_their_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (write(dir.path(), "src/a", "photo"), write(dir.path(), "src/b", "photo"));
+        let destination = dir.path().join("dest/a");
+        let mut index = DuplicateIndex::default();
+        index.add(&a, &destination, 5);
+
+        assert_eq!(index.find(&a, 5).unwrap(), None);
+        assert_eq!(index.find(&b, 5).unwrap(), Some(destination.clone()));
+
+        // Still found once the first file has been moved there
+        fs::create_dir_all(destination.parent().unwrap()).unwrap();
+        fs::rename(&a, &destination).unwrap();
+        let mut index = DuplicateIndex::default();
+        index.add(&a, &destination, 5);
+        assert_eq!(index.find(&b, 5).unwrap(), Some(destination.clone()));
+
+        index.remove(&a);
+        assert_eq!(index.find(&b, 5).unwrap(), None);
+    }
+
+    #[test]
+    fn duplicates_already_at_the_destination_are_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = write(dir.path(), "dest/old", "photo");
+        let claimed = write(dir.path(), "dest/claimed", "other");
+        let mut index = DuplicateIndex::default();
+        index.add_dir(&dir.path().join("dest"), |path| path == claimed).unwrap();
+        index.add_dir(&dir.path().join("nowhere"), |_| false).unwrap();
+
+        assert_eq!(index.find(&write(dir.path(), "src/a", "photo"), 5).unwrap(), Some(existing));
+        assert_eq!(index.find(&write(dir.path(), "src/b", "other"), 5).unwrap(), None);
+    }
+
+    #[test]
+    fn duplicates_are_grouped_in_the_order_of_the_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = vec![
+            write(dir.path(), "c", "one"),
+            write(dir.path(), "a", "two"),
+            write(dir.path(), "b", "one"),
+            write(dir.path(), "d", "six"),
+            write(dir.path(), "e", "two"),
+            write(dir.path(), "f", "single"),
+        ];
+        let mut failures = Vec::new();
+        let mut with_missing = paths.clone();
+        with_missing.push(dir.path().join("missing"));
+
+        let groups = find_duplicates(&with_missing, &mut failures);
+        assert_eq!(groups, [vec![paths[0].clone(), paths[2].clone()], vec![paths[1].clone(), paths[4].clone()]]);
+        assert_eq!(failures.len(), 1);
+    }
+}
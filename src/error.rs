@@ -0,0 +1,79 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// Exit code when the run could not start, e.g. because the source is missing
+pub const EXIT_ERROR: i32 = 1;
+/// Exit code when the config file is missing or invalid
+pub const EXIT_CONFIG_ERROR: i32 = 3;
+/// Exit code when the run completed but some files could not be handled
+pub const EXIT_INCOMPLETE: i32 = 4;
+
+#[derive(Debug)]
+pub enum KondoError {
+    /// The config file could not be read or is not valid TOML
+    ConfigFile { path: PathBuf, message: String },
+    /// A key in the config is missing or has an invalid value
+    Config { key: String, message: String },
+    /// No config file was given, and none was found in the usual places
+    NoConfig { searched: Vec<PathBuf> },
+    Io { path: PathBuf, source: std::io::Error },
+    PermissionDenied { path: PathBuf },
+    /// The run went through, but some files could not be handled
+    Incomplete { failed: usize, action: &'static str },
+}
+
+impl KondoError {
+    pub fn config(key: impl Into<String>, message: impl Into<String>) -> KondoError {
+        KondoError::Config { key: key.into(), message: message.into() }
+    }
+
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> KondoError {
+        let path = path.into();
+        match source.kind() {
+            std::io::ErrorKind::PermissionDenied => KondoError::PermissionDenied { path },
+            _ => KondoError::Io { path, source },
+        }
+    }
+
+    /// The process exit code the command line tool uses for this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            KondoError::ConfigFile { .. } | KondoError::Config { .. } | KondoError::NoConfig { .. } => {
+                EXIT_CONFIG_ERROR
+            }
+            KondoError::Incomplete { .. } => EXIT_INCOMPLETE,
+            KondoError::Io { .. } | KondoError::PermissionDenied { .. } => EXIT_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for KondoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KondoError::ConfigFile { path, message } => {
+                write!(f, "Cannot load config file {}: {}", path.display(), message)
+            }
+            KondoError::Config { key, message } => write!(f, "Invalid config key '{}': {}", key, message),
+            KondoError::NoConfig { searched } => {
+                let mut searched: Vec<String> = searched.iter().map(|path| path.display().to_string()).collect();
+                let last = searched.pop().unwrap_or_default();
+                let places = if searched.is_empty() { last } else { format!("{} or {}", searched.join(", "), last) };
+                write!(f, "No config file found at {}; pass --config, or write one with `kondo init`", places)
+            }
+            KondoError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            KondoError::PermissionDenied { path } => write!(f, "{}: permission denied", path.display()),
+            KondoError::Incomplete { failed, action } => {
+                write!(f, "{} file(s) could not be {}", failed, action)
+            }
+        }
+    }
+}
+
+impl std::error::Error for KondoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KondoError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
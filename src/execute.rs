@@ -0,0 +1,148 @@
+use crate::dupes::{is_identical, is_same_file};
+use crate::error::KondoError;
+use crate::journal::Journal;
+use crate::parallel::{parallel_map, Completion};
+use crate::plan::{Action, PlannedMove};
+use crate::transfer::{replace_with_hardlink, transfer};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// What became of a planned move
+#[derive(Debug)]
+pub enum Outcome {
+    /// The file was moved, copied or linked, as its mode says
+    Transferred,
+    /// A duplicate was deleted
+    Deleted,
+    /// A duplicate was replaced with a hard link
+    Linked,
+    Skipped(String),
+    Failed(KondoError),
+}
+
+/// Carries out planned moves, journaling each one so it can be undone. Moves
+/// may be carried out from several threads at once.
+pub struct Executor {
+    journal_path: PathBuf,
+    journal: Mutex<Option<Journal>>,
+}
+
+impl Executor {
+    pub fn new(journal_path: impl Into<PathBuf>) -> Executor {
+        Executor {
+            journal_path: journal_path.into(),
+            journal: Mutex::new(None),
+        }
+    }
+
+    /// Apply planned moves on `jobs` threads, as they come, and hand each
+    /// outcome to `report` in the order of `moves`. A duplicate is only
+    /// deleted or linked once the move bringing its identical file into
+    /// place is done, and a file only overwrites one moved earlier in the
+    /// run once that one is there. Stops at the first error from `execute`
+    /// or `report`.
+    pub fn execute_all(
+        &self,
+        moves: impl Iterator<Item = PlannedMove> + Send,
+        jobs: usize,
+        mut report: impl FnMut(&PlannedMove, Outcome) -> Result<(), KondoError>,
+    ) -> Result<(), KondoError> {
+        // Items are taken in order, so a move is always known, and taken,
+        // before the duplicates waiting for it
+        let mut moved_to = HashMap::new();
+        let moves = moves.enumerate().map(move |(index, planned)| {
+            let waits_for = match planned.action {
+                // A file replacing one moved earlier in the run goes in after it
+                Action::Move | Action::Overwrite => moved_to.insert(planned.destination.clone(), index),
+                Action::Delete | Action::Hardlink => moved_to.get(&planned.destination).copied(),
+                Action::Skip(_) => None,
+            };
+            (index, planned, waits_for)
+        });
+
+        let completion = Completion::default();
+        parallel_map(
+            moves,
+            jobs,
+            |(index, planned, waits_for)| {
+                if let Some(earlier) = waits_for {
+                    completion.wait_for(earlier);
+                }
+                let outcome = self.execute(&planned);
+                completion.finish(index);
+                (planned, outcome)
+            },
+            |(planned, outcome)| report(&planned, outcome?),
+        )
+    }
+
+    /// Apply one planned move. Failing to move the file is reported in the
+    /// outcome; failing to journal it is an error, as the run could no longer
+    /// be undone.
+    pub fn execute(&self, planned: &PlannedMove) -> Result<Outcome, KondoError> {
+        let (source, destination) = (&planned.source, &planned.destination);
+        match &planned.action {
+            Action::Skip(reason) => return Ok(Outcome::Skipped(reason.clone())),
+            // Never replace a file that appeared after the plan was made
+            Action::Move if destination.exists() => {
+                return Ok(Outcome::Skipped("destination appeared meanwhile".to_string()));
+            }
+            Action::Delete | Action::Hardlink => return Ok(remove_duplicate(planned)),
+            Action::Move | Action::Overwrite => {}
+        }
+
+        if let Err(e) = transfer(source, destination, planned.mode) {
+            return Ok(Outcome::Failed(KondoError::io(source, e)));
+        }
+
+        // Only start a new journal once something has been transferred, so
+        // that a run which changes nothing keeps the previous run undoable
+        let mut journal = self.journal.lock().unwrap_or_else(|e| e.into_inner());
+        let journal = match &mut *journal {
+            Some(journal) => journal,
+            None => {
+                let created = Journal::create(&self.journal_path)
+                    .map_err(|e| KondoError::io(&self.journal_path, e))?;
+                journal.insert(created)
+            }
+        };
+
+        journal
+            .record(source, destination, planned.mode)
+            .map_err(|e| KondoError::io(&self.journal_path, e))?;
+
+        Ok(Outcome::Transferred)
+    }
+}
+
+// Delete or hard link a duplicate. Neither is journaled, as the content stays
+// available at the destination.
+fn remove_duplicate(planned: &PlannedMove) -> Outcome {
+    let (source, original) = (&planned.source, &planned.destination);
+
+    // Files may have changed since the plan was made, and a file is only ever
+    // removed while an identical copy is known to exist
+    if !original.exists() {
+        return Outcome::Skipped("the identical file is gone".to_string());
+    }
+    if planned.action == Action::Hardlink && is_same_file(source, original).unwrap_or(false) {
+        return Outcome::Skipped("already linked".to_string());
+    }
+    match is_identical(source, original) {
+        Ok(true) => {}
+        Ok(false) => return Outcome::Skipped("no longer identical".to_string()),
+        Err(e) => return Outcome::Failed(KondoError::io(source, e)),
+    }
+
+    let (result, outcome) = match planned.action {
+        Action::Hardlink => (replace_with_hardlink(source, original), Outcome::Linked),
+        _ => (fs::remove_file(source), Outcome::Deleted),
+    };
+
+    match result {
+        Ok(()) => outcome,
+        Err(e) => Outcome::Failed(KondoError::io(source, e)),
+    }
+}
@@ -0,0 +1,109 @@
+use crate::config::lookup_var;
+use std::path::{Path, PathBuf};
+
+/// A commented config to start from. Files go to the XDG user directories,
+/// as set in the environment or in `user-dirs.dirs`, or else to their usual
+/// names in the home directory.
+pub fn starter_config() -> String {
+    let dir = |name: &str, fallback: &str| user_dir(name).unwrap_or_else(|| fallback.to_string());
+    let pictures = dir("XDG_PICTURES_DIR", "~/Pictures");
+    let documents = dir("XDG_DOCUMENTS_DIR", "~/Documents");
+    let music = dir("XDG_MUSIC_DIR", "~/Music");
+    let videos = dir("XDG_VIDEOS_DIR", "~/Videos");
+    let downloads = dir("XDG_DOWNLOAD_DIR", "~/Downloads");
+    let desktop = dir("XDG_DESKTOP_DIR", "~/Desktop");
+
+    format!(
+        r#"# Kondo config, written by `kondo init`
+#
+# Files go to the category listing their extension, the longest one matching
+# when several do (tar.gz over gz), and are left alone when none does. List an
+# extension in one category only. Destinations may use ~, $VARIABLES and
+# placeholders filled in for each file, such as {{year}}, {{month:02}},
+# {{artist}} or {{album}}.
+# Run `kondo config validate` after editing to check the file.
+
+[options]
+# What to do when a file of the same name is already at the destination:
+# skip, overwrite, rename, timestamp, newer or larger
+on_conflict = "rename"
+# Move, copy, symlink or hardlink files to their destination
+mode = "move"
+# Leave downloads in progress alone, in .gitignore syntax
+ignore = ["*.part", "*.crdownload"]
+# Also recognise files by their content, e.g. those without an extension
+# sniff = true
+
+[categories.images]
+extensions = ["jpg", "jpeg", "png", "gif", "bmp", "tiff", "webp", "heic"]
+destination = {images}
+
+[categories.documents]
+extensions = ["pdf", "doc", "docx", "txt", "rtf", "odt", "xls", "xlsx", "ppt", "pptx"]
+destination = {documents}
+
+[categories.audio]
+extensions = ["mp3", "wav", "ogg", "flac", "aac", "m4a"]
+destination = {audio}
+# Name tracks from their tags, keeping the extension
+rename = "{{track:02}} - {{title}}"
+
+[categories.video]
+extensions = ["mp4", "mkv", "avi", "mov", "webm"]
+destination = {videos}
+
+[categories.archives]
+extensions = ["zip", "tar", "tar.gz", "tgz", "gz", "7z", "rar"]
+destination = "~/Archives"
+
+# `kondo run downloads` organizes the downloads folder, and `kondo run --all`
+# every profile. A profile may have categories and options of its own.
+[profiles.downloads]
+sources = [{downloads}]
+
+# [profiles.desktop]
+# sources = [{desktop}]
+"#,
+        images = quoted(&format!("{}/{{year}}/{{month:02}}", pictures)),
+        documents = quoted(&documents),
+        audio = quoted(&format!("{}/{{artist}}/{{album}}", music)),
+        videos = quoted(&videos),
+        downloads = quoted(&downloads),
+        desktop = quoted(&desktop),
+    )
+}
+
+// A user directory as written in the config, under `~` when in the home
+// directory. A directory set to the home directory itself, which is how
+// `user-dirs.dirs` turns one off, counts as missing.
+fn user_dir(name: &str) -> Option<String> {
+    let dir = PathBuf::from(lookup_var(name)?);
+    let home = lookup_var("HOME").map(PathBuf::from);
+
+    let written = match home.as_deref().and_then(|home| dir.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => return None,
+        Some(rest) => Path::new("~").join(rest),
+        None => dir,
+    };
+    // Braces would be taken for placeholders
+    Some(written.to_string_lossy().replace('{', "{{").replace('}', "}}"))
+}
+
+// A TOML string, quoted and escaped
+fn quoted(value: &str) -> String {
+    toml::Value::String(value.to_string()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn the_starter_config_is_valid() {
+        let config = Config::parse(&starter_config(), Path::new("/base")).unwrap();
+        assert!(config.warnings.is_empty(), "{:?}", config.warnings);
+        assert_eq!(config.categories.len(), 5);
+        assert_eq!(config.profiles[0].name, "downloads");
+    }
+}
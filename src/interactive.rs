@@ -0,0 +1,648 @@
+use crate::classify::{classify_files, get_extension, matching_extension};
+use crate::config::{Category, Config, LEGACY_CATEGORIES};
+use crate::error::KondoError;
+use crate::execute::{Executor, Outcome};
+use crate::plan::{Action, PlannedMove, Planner};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml_edit::{Array, DocumentMut, Item, Table, TableLike, Value};
+
+/// What `--interactive` asks about
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InteractiveMode {
+    /// Every planned move on its own
+    #[default]
+    File,
+    /// All the files of a category at once
+    Category,
+}
+
+impl InteractiveMode {
+    pub const NAMES: [&'static str; 2] = ["file", "category"];
+}
+
+impl FromStr for InteractiveMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<InteractiveMode, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "file" => Ok(InteractiveMode::File),
+            "category" => Ok(InteractiveMode::Category),
+            _ => Err(format!("unknown interactive mode '{}'", s)),
+        }
+    }
+}
+
+/// What the user chose for a move or a category
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Choice {
+    Accept,
+    Skip,
+    /// Put the files in the named category instead
+    Change(String),
+    /// Leave this and all the remaining files alone
+    Quit,
+}
+
+/// An answer to remember for every file with a given extension
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Answer {
+    Accept,
+    Skip,
+    /// Put the files in the named category
+    Category(String),
+}
+
+/// Answers remembered during a session, by lowercase extension
+pub type Remembered = BTreeMap<String, Answer>;
+
+/// Asks the questions of `--interactive` on `output` and reads the answers,
+/// one per line, from `input`. The end of the input counts as quitting.
+pub struct Prompter<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Prompter<R, W> {
+    pub fn new(input: R, output: W) -> Prompter<R, W> {
+        Prompter { input, output }
+    }
+
+    /// Ask what to do, offering to pick another of `categories`. With an
+    /// extension, the answer may also be given for every file having it, in
+    /// which case it is returned along with the choice.
+    pub fn choose(
+        &mut self,
+        categories: &[&str],
+        extension: Option<&str>,
+    ) -> io::Result<(Choice, Option<Answer>)> {
+        let always = match extension {
+            Some(extension) => format!(" (A, S or C: always for .{})", extension),
+            None => String::new(),
+        };
+
+        loop {
+            let question = format!("[a]ccept, [s]kip, [c]hange category, [q]uit{}? ", always);
+            let Some(reply) = self.ask(&question)? else {
+                return Ok((Choice::Quit, None));
+            };
+
+            let remember = extension.is_some() && reply.len() == 1 && reply.chars().all(|c| c.is_ascii_uppercase());
+            let choice = match reply.to_ascii_lowercase().as_str() {
+                "a" | "accept" => Choice::Accept,
+                "s" | "skip" => Choice::Skip,
+                "c" | "change" => match self.pick_category(categories)? {
+                    Some(name) => Choice::Change(name),
+                    None => continue,
+                },
+                "q" | "quit" => return Ok((Choice::Quit, None)),
+                _ => {
+                    writeln!(self.output, "Please answer a, s, c or q")?;
+                    continue;
+                }
+            };
+
+            let answer = remember.then(|| match &choice {
+                Choice::Accept => Answer::Accept,
+                Choice::Skip => Answer::Skip,
+                Choice::Change(name) => Answer::Category(name.clone()),
+                Choice::Quit => unreachable!("quitting returns above"),
+            });
+            return Ok((choice, answer));
+        }
+    }
+
+    /// Ask for one of `categories`, by number or name. `None` if the user
+    /// changed their mind.
+    pub fn pick_category(&mut self, categories: &[&str]) -> io::Result<Option<String>> {
+        for (number, name) in categories.iter().enumerate() {
+            writeln!(self.output, "  {}) {}", number + 1, name)?;
+        }
+
+        loop {
+            let Some(reply) = self.ask("Category (number or name, empty to go back): ")? else {
+                return Ok(None);
+            };
+            if reply.is_empty() {
+                return Ok(None);
+            }
+
+            let by_number = reply.parse::<usize>().ok().and_then(|n| n.checked_sub(1)).and_then(|i| categories.get(i));
+            let by_name = categories.iter().find(|name| name.eq_ignore_ascii_case(&reply));
+            match by_number.or(by_name) {
+                Some(name) => return Ok(Some(name.to_string())),
+                None => writeln!(self.output, "No category '{}'", reply)?,
+            }
+        }
+    }
+
+    /// Ask a yes or no question, no being the default
+    pub fn confirm(&mut self, question: &str) -> io::Result<bool> {
+        let reply = self.ask(&format!("{} [y/N] ", question))?;
+        Ok(reply.is_some_and(|reply| matches!(reply.to_ascii_lowercase().as_str(), "y" | "yes")))
+    }
+
+    /// Write to the output, e.g. the move a question is about
+    pub fn say(&mut self, text: &str) -> io::Result<()> {
+        writeln!(self.output, "{}", text)
+    }
+
+    // The trimmed reply, or `None` at the end of the input
+    fn ask(&mut self, question: &str) -> io::Result<Option<String>> {
+        write!(self.output, "{}", question)?;
+        self.output.flush()?;
+
+        let mut reply = String::new();
+        if self.input.read_line(&mut reply)? == 0 {
+            writeln!(self.output)?;
+            return Ok(None);
+        }
+        Ok(Some(reply.trim().to_string()))
+    }
+}
+
+/// Organize `paths` the way `--interactive` does: show how the files are
+/// classified, along with any warnings, then ask about each move, or each category, through
+/// `prompter` before carrying it out. Answers given for every file of an
+/// extension may be saved to the config at `config_path` at the end. The
+/// outcome of each move goes to `report`, and the files that could not be
+/// classified or planned are returned.
+pub fn organize_interactively<R: BufRead, W: Write>(
+    paths: Vec<PathBuf>,
+    config: &Config,
+    config_path: &Path,
+    executor: &Executor,
+    mode: InteractiveMode,
+    prompter: &mut Prompter<R, W>,
+    mut report: impl FnMut(&PlannedMove, Outcome),
+) -> Result<Vec<KondoError>, KondoError> {
+    let mut warnings = Vec::new();
+    let mut failures = Vec::new();
+    let classified: Vec<(PathBuf, &Category)> = classify_files(config, paths, &mut warnings, &mut failures)
+        .into_iter()
+        .filter_map(|(path, category)| Some((path, category?)))
+        .collect();
+
+    for warning in &warnings {
+        prompter.say(&format!("Warning: {}", warning)).map_err(prompt_error)?;
+    }
+    say_classification(prompter, &classified)?;
+
+    let mut remembered = Remembered::new();
+    let asked = match mode {
+        InteractiveMode::File => {
+            ask_per_file(config, executor, classified, prompter, &mut remembered, &mut failures, &mut report)
+        }
+        InteractiveMode::Category => {
+            ask_per_category(config, executor, classified, prompter, &mut failures, &mut report)
+        }
+    };
+    asked?;
+
+    if has_config_changes(&remembered) {
+        let extensions: Vec<String> = remembered
+            .iter()
+            .filter(|(_, answer)| **answer != Answer::Accept)
+            .map(|(extension, _)| format!(".{}", extension))
+            .collect();
+        let question = format!("Save the answers for {} to {}?", extensions.join(", "), config_path.display());
+        if prompter.confirm(&question).map_err(prompt_error)? {
+            save_answers(config_path, &remembered)?;
+        }
+    }
+
+    Ok(failures)
+}
+
+// Questions and answers go through the terminal
+fn prompt_error(e: io::Error) -> KondoError {
+    KondoError::io("standard input", e)
+}
+
+fn say_classification<R: BufRead, W: Write>(
+    prompter: &mut Prompter<R, W>,
+    classified: &[(PathBuf, &Category)],
+) -> Result<(), KondoError> {
+    let mut by_category: Vec<(&str, Vec<&Path>)> = Vec::new();
+    for (path, category) in classified {
+        match by_category.iter_mut().find(|(name, _)| *name == category.name) {
+            Some((_, paths)) => paths.push(path),
+            None => by_category.push((&category.name, vec![path])),
+        }
+    }
+
+    let mut text = String::from("Classification:");
+    for (name, paths) in &by_category {
+        text.push_str(&format!("\n  {} ({} file(s))", name, paths.len()));
+        for path in paths {
+            text.push_str(&format!("\n    {}", path.display()));
+        }
+    }
+    if by_category.is_empty() {
+        text.push_str("\n  no files to organize");
+    }
+    prompter.say(&format!("{}\n", text)).map_err(prompt_error)
+}
+
+fn category_names(config: &Config) -> Vec<&str> {
+    config.categories.iter().map(|category| category.name.as_str()).collect()
+}
+
+fn find_category<'a>(config: &'a Config, name: &str) -> Option<&'a Category> {
+    config.categories.iter().find(|category| category.name == name)
+}
+
+// Ask about every planned move, carrying it out right away once accepted.
+// Answers given for all files of an extension are applied without asking.
+fn ask_per_file<R: BufRead, W: Write>(
+    config: &Config,
+    executor: &Executor,
+    classified: Vec<(PathBuf, &Category)>,
+    prompter: &mut Prompter<R, W>,
+    remembered: &mut Remembered,
+    failures: &mut Vec<KondoError>,
+    report: &mut impl FnMut(&PlannedMove, Outcome),
+) -> Result<(), KondoError> {
+    let names = category_names(config);
+    let mut planner = Planner::new(&config.options);
+    // So that answers for `backup.tar.gz` are given for `tar.gz`, as the
+    // classifier would match it
+    let known: Vec<String> = config.categories.iter().flat_map(|c| c.extensions.iter().cloned()).collect();
+
+    'files: for (path, mut category) in classified {
+        let extension = matching_extension(&path, &known).map(str::to_string).or_else(|| get_extension(&path));
+
+        loop {
+            let planned = match planner.plan_move(path.clone(), category) {
+                Ok(planned) => planned,
+                Err(e) => {
+                    failures.push(KondoError::io(&path, e));
+                    continue 'files;
+                }
+            };
+            if let Action::Skip(reason) = &planned.action {
+                report(&planned, Outcome::Skipped(reason.clone()));
+                continue 'files;
+            }
+
+            let choice = match extension.as_ref().and_then(|extension| remembered.get(extension)) {
+                Some(Answer::Accept) => Choice::Accept,
+                Some(Answer::Skip) => Choice::Skip,
+                Some(Answer::Category(name)) if *name == category.name => Choice::Accept,
+                Some(Answer::Category(name)) => Choice::Change(name.clone()),
+                None => {
+                    prompter.say(&describe_move(&planned)).map_err(prompt_error)?;
+                    let (choice, answer) = prompter.choose(&names, extension.as_deref()).map_err(prompt_error)?;
+                    if let (Some(extension), Some(answer)) = (&extension, answer) {
+                        remembered.insert(extension.clone(), answer);
+                    }
+                    choice
+                }
+            };
+
+            match choice {
+                Choice::Accept => {
+                    let outcome = executor.execute(&planned)?;
+                    report(&planned, outcome);
+                    continue 'files;
+                }
+                Choice::Skip => {
+                    planner.forget(&planned);
+                    report(&planned, Outcome::Skipped("as answered".to_string()));
+                    continue 'files;
+                }
+                Choice::Change(name) => {
+                    planner.forget(&planned);
+                    category = find_category(config, &name).unwrap_or(category);
+                }
+                Choice::Quit => {
+                    planner.forget(&planned);
+                    break 'files;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Ask about each category's files at once, then plan and carry out the moves
+// accepted, in the order of the files
+fn ask_per_category<R: BufRead, W: Write>(
+    config: &Config,
+    executor: &Executor,
+    classified: Vec<(PathBuf, &Category)>,
+    prompter: &mut Prompter<R, W>,
+    failures: &mut Vec<KondoError>,
+    report: &mut impl FnMut(&PlannedMove, Outcome),
+) -> Result<(), KondoError> {
+    let names = category_names(config);
+
+    let mut by_category: Vec<(&Category, Vec<PathBuf>)> = Vec::new();
+    for (path, category) in classified {
+        match by_category.iter_mut().find(|(c, _)| c.name == category.name) {
+            Some((_, paths)) => paths.push(path),
+            None => by_category.push((category, vec![path])),
+        }
+    }
+
+    let mut accepted = Vec::new();
+    for (category, paths) in by_category {
+        prompter.say(&format!("{}: {} file(s) to {}", category.name, paths.len(), category.destination.display()))
+            .map_err(prompt_error)?;
+        match prompter.choose(&names, None).map_err(prompt_error)?.0 {
+            Choice::Accept => accepted.extend(paths.into_iter().map(|path| (path, category))),
+            Choice::Skip => {}
+            Choice::Change(name) => {
+                let category = find_category(config, &name).unwrap_or(category);
+                accepted.extend(paths.into_iter().map(|path| (path, category)));
+            }
+            Choice::Quit => break,
+        }
+    }
+
+    accepted.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut planner = Planner::new(&config.options);
+    for (path, category) in accepted {
+        let planned = match planner.plan_move(path.clone(), category) {
+            Ok(planned) => planned,
+            Err(e) => {
+                failures.push(KondoError::io(&path, e));
+                continue;
+            }
+        };
+        let outcome = executor.execute(&planned)?;
+        report(&planned, outcome);
+    }
+
+    Ok(())
+}
+
+// e.g. `documents: move a.pdf -> ~/Documents/a.pdf`, the move a question is about
+fn describe_move(planned: &PlannedMove) -> String {
+    let (category, verb) = (&planned.category, planned.mode.verb());
+    let (source, destination) = (planned.source.display(), planned.destination.display());
+    match planned.action {
+        Action::Delete => format!("{}: delete duplicate {} (same as {})", category, source, destination),
+        Action::Hardlink => format!("{}: link duplicate {} -> {}", category, source, destination),
+        Action::Move if planned.duplicate_of.is_some() => {
+            format!("{}: move duplicate {} -> {}", category, source, destination)
+        }
+        Action::Overwrite => format!("{}: {} {} -> {} (overwriting)", category, verb, source, destination),
+        _ => format!("{}: {} {} -> {}", category, verb, source, destination),
+    }
+}
+
+/// Whether any of the remembered answers would change the config. Accepting
+/// files is what the config does already.
+pub fn has_config_changes(remembered: &Remembered) -> bool {
+    remembered.values().any(|answer| *answer != Answer::Accept)
+}
+
+/// Write remembered answers into the config file, keeping its comments and
+/// layout. An extension always put in another category is moved to that
+/// category's `extensions`, and one always skipped is added to `exclude`.
+pub fn save_answers(config_path: &Path, remembered: &Remembered) -> Result<(), KondoError> {
+    let config_error = |message: String| KondoError::ConfigFile {
+        path: config_path.to_path_buf(),
+        message,
+    };
+
+    let text = fs::read_to_string(config_path).map_err(|e| KondoError::io(config_path, e))?;
+    let mut document: DocumentMut = text.parse().map_err(|e: toml_edit::TomlError| config_error(e.to_string()))?;
+
+    // Categories of a config in the old format only exist once written out
+    if remembered.values().any(|answer| matches!(answer, Answer::Category(_))) {
+        migrate_directories(&mut document).map_err(config_error)?;
+    }
+
+    for (extension, answer) in remembered {
+        match answer {
+            Answer::Accept => {}
+            Answer::Skip => {
+                let exclude = array_at(&mut document, "options", None, "exclude").map_err(config_error)?;
+                push_unique(exclude, &format!("*.{}", extension));
+            }
+            Answer::Category(name) => {
+                // An extension belongs to one category only
+                if let Some(categories) = document.get_mut("categories").and_then(Item::as_table_like_mut) {
+                    for (_, category) in categories.iter_mut() {
+                        let extensions = category.get_mut("extensions").and_then(Item::as_array_mut);
+                        if let Some(extensions) = extensions {
+                            extensions.retain(|v| !v.as_str().is_some_and(|e| e.eq_ignore_ascii_case(extension)));
+                        }
+                    }
+                }
+
+                let extensions =
+                    array_at(&mut document, "categories", Some(name), "extensions").map_err(config_error)?;
+                push_unique(extensions, extension);
+            }
+        }
+    }
+
+    fs::write(config_path, document.to_string()).map_err(|e| KondoError::io(config_path, e))
+}
+
+// Replace the old `[directories]` table, when there are no categories, with
+// the categories it stands for, so that extensions can be moved between them
+fn migrate_directories(document: &mut DocumentMut) -> Result<(), String> {
+    if document.contains_key("categories") {
+        return Ok(());
+    }
+    let Some(directories) = document.remove("directories") else {
+        return Ok(());
+    };
+    let directories = directories.as_table().ok_or("'directories' is not a table")?;
+    // The categories take the place of the old table, and its comments
+    let mut comments = directories.decor().prefix().cloned();
+    let position = directories.position();
+
+    let mut categories = Table::new();
+    categories.set_implicit(true);
+    for (name, destination) in directories.iter() {
+        let (_, extensions) = LEGACY_CATEGORIES
+            .iter()
+            .find(|(legacy, _)| *legacy == name)
+            .ok_or_else(|| format!("'directories.{}' is not one of images, documents or audio", name))?;
+
+        let mut category = Table::new();
+        if let Some(position) = position {
+            category.set_position(position);
+        }
+        if let Some(comments) = comments.take() {
+            category.decor_mut().set_prefix(comments);
+        }
+        category.insert("extensions", Item::Value(Value::Array(extensions.iter().copied().collect())));
+        category.insert("destination", destination.clone());
+        categories.insert(name, Item::Table(category));
+    }
+    document.insert("categories", Item::Table(categories));
+    Ok(())
+}
+
+// The array at `table.key`, or `table.subtable.key`, created if missing
+fn array_at<'a>(
+    document: &'a mut DocumentMut,
+    table: &str,
+    subtable: Option<&str>,
+    key: &str,
+) -> Result<&'a mut Array, String> {
+    let mut path = table.to_string();
+    let mut table = table_at(document.as_table_mut(), table, &path)?;
+    if let Some(subtable) = subtable {
+        path = format!("{}.{}", path, subtable);
+        table = table_at(table, subtable, &path)?;
+    }
+
+    let array = table.entry(key).or_insert(Item::Value(Value::Array(Array::new())));
+    array.as_array_mut().ok_or_else(|| format!("'{}.{}' is not an array", path, key))
+}
+
+// The table at `key` in `parent`, created if missing
+fn table_at<'a>(parent: &'a mut dyn TableLike, key: &str, path: &str) -> Result<&'a mut dyn TableLike, String> {
+    let mut table = Table::new();
+    // Only show a header for the new table if it gets keys of its own
+    table.set_implicit(true);
+
+    let item = parent.entry(key).or_insert(Item::Table(table));
+    item.as_table_like_mut().ok_or_else(|| format!("'{}' is not a table", path))
+}
+
+fn push_unique(array: &mut Array, value: &str) {
+    if !array.iter().any(|v| v.as_str() == Some(value)) {
+        array.push(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const CONFIG: &str = r#"[categories.documents]
+extensions = ["txt"]
+destination = "Documents"
+
+[categories.images]
+extensions = ["png"]
+destination = "Images"
+
+[categories.archives]
+extensions = ["tar.gz"]
+destination = "Archives"
+"#;
+
+    // Organize the files of `dir/src` with `answers` typed in, returning
+    // what was said and the outcomes
+    fn organize(dir: &Path, mode: InteractiveMode, answers: &str) -> (String, Vec<Outcome>) {
+        let config_path = dir.join("config.toml");
+        fs::write(&config_path, CONFIG).unwrap();
+        let config = Config::load(&config_path).unwrap();
+        let executor = Executor::new(dir.join("journal.toml"));
+
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir.join("src")).unwrap().map(|e| e.unwrap().path()).collect();
+        paths.sort();
+        let mut said = Vec::new();
+        let mut prompter = Prompter::new(Cursor::new(answers), &mut said);
+        let mut outcomes = Vec::new();
+        let report = |_: &PlannedMove, outcome| outcomes.push(outcome);
+        let failures = organize_interactively(paths, &config, &config_path, &executor, mode, &mut prompter, report);
+
+        assert!(failures.unwrap().is_empty());
+        (String::from_utf8(said).unwrap(), outcomes)
+    }
+
+    fn source_with(dir: &Path, names: &[&str]) {
+        fs::create_dir(dir.join("src")).unwrap();
+        for name in names {
+            fs::write(dir.join("src").join(name), name).unwrap();
+        }
+    }
+
+    #[test]
+    fn each_file_is_asked_about() {
+        let dir = tempfile::tempdir().unwrap();
+        source_with(dir.path(), &["a.txt", "b.txt"]);
+
+        let (said, outcomes) = organize(dir.path(), InteractiveMode::File, "a\ns\n");
+        assert!(said.starts_with("Classification:\n  documents (2 file(s))"));
+        assert!(matches!(outcomes[..], [Outcome::Transferred, Outcome::Skipped(_)]));
+        assert!(dir.path().join("Documents/a.txt").exists());
+        assert!(dir.path().join("src/b.txt").exists());
+    }
+
+    #[test]
+    fn answers_for_an_extension_are_saved_when_confirmed() {
+        let dir = tempfile::tempdir().unwrap();
+        source_with(dir.path(), &["a.txt", "b.txt"]);
+
+        let (_, outcomes) = organize(dir.path(), InteractiveMode::File, "C\nimages\ny\n");
+        assert!(matches!(outcomes[..], [Outcome::Transferred, Outcome::Transferred]));
+        assert!(dir.path().join("Images/b.txt").exists());
+
+        // The answer was given for a.txt, so b.txt went without asking
+        let config = Config::load(&dir.path().join("config.toml")).unwrap();
+        let images = config.categories.iter().find(|c| c.name == "images").unwrap();
+        assert!(images.extensions.contains(&"txt".to_string()));
+    }
+
+    #[test]
+    fn answers_are_remembered_for_the_longest_known_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        source_with(dir.path(), &["a.tar.gz", "b.tar.gz"]);
+
+        let (said, _) = organize(dir.path(), InteractiveMode::File, "C\nimages\ny\n");
+        assert!(said.contains("Save the answers for .tar.gz to"), "{}", said);
+        assert!(dir.path().join("Images/b.tar.gz").exists());
+
+        let config = Config::load(&dir.path().join("config.toml")).unwrap();
+        let images = config.categories.iter().find(|c| c.name == "images").unwrap();
+        let archives = config.categories.iter().find(|c| c.name == "archives").unwrap();
+        assert_eq!(images.extensions, ["png", "tar.gz"]);
+        assert!(archives.extensions.is_empty());
+    }
+
+    #[test]
+    fn saving_answers_writes_out_the_categories_of_the_old_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        fs::write(&config_path, "[directories]\nimages = \"/p\"\ndocuments = \"/d\"\n").unwrap();
+
+        let remembered = Remembered::from([("rtf".to_string(), Answer::Category("images".to_string()))]);
+        save_answers(&config_path, &remembered).unwrap();
+
+        let config = Config::load(&config_path).unwrap();
+        assert!(config.warnings.is_empty(), "{:?}", config.warnings);
+        let images = config.categories.iter().find(|c| c.name == "images").unwrap();
+        let documents = config.categories.iter().find(|c| c.name == "documents").unwrap();
+        assert!(images.extensions.contains(&"rtf".to_string()));
+        assert!(!documents.extensions.contains(&"rtf".to_string()));
+        assert_eq!(images.destination, Path::new("/p"));
+    }
+
+    #[test]
+    fn each_category_is_asked_about_once() {
+        let dir = tempfile::tempdir().unwrap();
+        source_with(dir.path(), &["a.png", "b.txt", "c.txt"]);
+
+        let (said, outcomes) = organize(dir.path(), InteractiveMode::Category, "s\na\n");
+        assert!(said.contains("documents: 2 file(s) to"));
+        assert_eq!(outcomes.len(), 2);
+        assert!(dir.path().join("src/a.png").exists());
+        assert!(dir.path().join("Documents/c.txt").exists());
+    }
+
+    #[test]
+    fn the_end_of_the_input_quits() {
+        let dir = tempfile::tempdir().unwrap();
+        source_with(dir.path(), &["a.txt"]);
+
+        let (_, outcomes) = organize(dir.path(), InteractiveMode::File, "");
+        assert!(outcomes.is_empty());
+        assert!(dir.path().join("src/a.txt").exists());
+    }
+}
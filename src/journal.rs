@@ -0,0 +1,207 @@
+use crate::error::KondoError;
+use crate::dupes::{is_identical, is_same_file};
+use crate::transfer::{is_link_to, move_file, TransferMode};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// `$XDG_STATE_HOME/kondo/journal.toml`, or `~/.local/state/kondo/journal.toml`
+pub fn default_journal_path() -> PathBuf {
+    let state_dir = env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    state_dir.join("kondo").join("journal.toml")
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct JournalFile {
+    #[serde(default)]
+    moves: Vec<JournalEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JournalEntry {
+    timestamp: String,
+    from: PathBuf,
+    to: PathBuf,
+    // Journals written before transfer modes existed only hold moves
+    #[serde(default)]
+    mode: TransferMode,
+}
+
+// Record of the transfers performed by a run, so that `kondo undo` can revert them.
+// Every entry is appended as its own `[[moves]]` table and flushed straight away,
+// so the journal stays usable even if the run is interrupted.
+pub(crate) struct Journal {
+    file: File,
+}
+
+impl Journal {
+    pub(crate) fn create(path: &Path) -> std::io::Result<Journal> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        Ok(Journal { file: File::create(path)? })
+    }
+
+    pub(crate) fn record(&mut self, from: &Path, to: &Path, mode: TransferMode) -> std::io::Result<()> {
+        // Undo may well be run from another working directory
+        let from = std::path::absolute(from)?;
+        let to = std::path::absolute(to)?;
+
+        let entry = JournalEntry {
+            timestamp: Local::now().to_rfc3339(),
+            from,
+            to,
+            mode,
+        };
+        self.file.write_all(format_journal(vec![entry])?.as_bytes())?;
+        self.file.flush()
+    }
+}
+
+fn format_journal(moves: Vec<JournalEntry>) -> std::io::Result<String> {
+    let journal = toml::to_string(&JournalFile { moves })
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    Ok(format!("{}\n", journal))
+}
+
+/// Revert the transfers recorded in the journal, most recent first: moved files
+/// are moved back, and copies and links are removed. `report` is called with
+/// the destination and the source of every file, how it got there, and whether
+/// it could be reverted. Entries that could not be reverted are kept in the
+/// journal so the undo can be retried; their number is returned.
+pub fn undo_last_run(
+    journal_path: &Path,
+    mut report: impl FnMut(&Path, &Path, TransferMode, Result<(), String>),
+) -> Result<usize, KondoError> {
+    let journal_error = |e| KondoError::io(journal_path, e);
+
+    let journal: JournalFile = fs::read_to_string(journal_path)
+        .map_err(journal_error)
+        .and_then(|text| {
+            toml::from_str(&text)
+                .map_err(|e| journal_error(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))
+        })?;
+
+    let mut failed = Vec::new();
+
+    // Replay the journal backwards so the most recent move is reverted first
+    for entry in journal.moves.into_iter().rev() {
+        let (from, to) = (&entry.from, &entry.to);
+
+        let result = if entry.mode == TransferMode::Move {
+            if !to.exists() {
+                Err(format!("{} no longer exists", to.display()))
+            } else if from.exists() {
+                Err(format!("{} is already taken", from.display()))
+            } else {
+                move_file(to, from).map_err(|e| e.to_string())
+            }
+        } else {
+            remove_transferred(from, to, entry.mode)
+        };
+
+        let restored = result.is_ok();
+        report(to, from, entry.mode, result);
+        if !restored {
+            failed.push(entry);
+        }
+    }
+
+    if failed.is_empty() {
+        fs::remove_file(journal_path).map_err(journal_error)?;
+        return Ok(0);
+    }
+
+    // Keep whatever could not be restored, so the undo can be retried
+    let count = failed.len();
+    failed.reverse();
+    let remaining = format_journal(failed).map_err(journal_error)?;
+    fs::write(journal_path, remaining).map_err(journal_error)?;
+
+    Ok(count)
+}
+
+// Remove a copy or link, as long as nothing is lost by doing so
+fn remove_transferred(from: &Path, to: &Path, mode: TransferMode) -> Result<(), String> {
+    if fs::symlink_metadata(to).is_err() {
+        return Err(format!("{} no longer exists", to.display()));
+    }
+
+    let unchanged = match mode {
+        TransferMode::Copy => is_identical(from, to).unwrap_or(false),
+        TransferMode::Symlink => is_link_to(to, from),
+        TransferMode::Hardlink => is_same_file(from, to).unwrap_or(false),
+        TransferMode::Move => unreachable!("moves are moved back"),
+    };
+    if !unchanged {
+        return Err(format!("{} no longer matches {}", to.display(), from.display()));
+    }
+
+    fs::remove_file(to).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transfer::transfer;
+
+    // Transfer each file and journal it, as a run does
+    fn run(journal_path: &Path, transfers: &[(&Path, &Path, TransferMode)]) {
+        let mut journal = Journal::create(journal_path).unwrap();
+        for (from, to, mode) in transfers {
+            transfer(from, to, *mode).unwrap();
+            journal.record(from, to, *mode).unwrap();
+        }
+    }
+
+    #[test]
+    fn undo_reverts_moves_and_removes_copies() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        let (moved, copied) = (dir.path().join("dest/a.txt"), dir.path().join("dest/b.txt"));
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+        let journal_path = dir.path().join("journal.toml");
+        run(&journal_path, &[(&a, &moved, TransferMode::Move), (&b, &copied, TransferMode::Copy)]);
+
+        let mut reverted = Vec::new();
+        let failed = undo_last_run(&journal_path, |to, _, _, result| reverted.push((to.to_path_buf(), result)));
+
+        assert_eq!(failed.unwrap(), 0);
+        assert_eq!(reverted, [(copied.clone(), Ok(())), (moved.clone(), Ok(()))]);
+        assert_eq!(fs::read_to_string(&a).unwrap(), "a");
+        assert!(b.exists() && !copied.exists() && !moved.exists());
+        assert!(!journal_path.exists());
+    }
+
+    #[test]
+    fn entries_that_cannot_be_reverted_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        let (moved_a, moved_b) = (dir.path().join("dest/a.txt"), dir.path().join("dest/b.txt"));
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+        let journal_path = dir.path().join("journal.toml");
+        run(&journal_path, &[(&a, &moved_a, TransferMode::Move), (&b, &moved_b, TransferMode::Move)]);
+
+        // Something else took the place of a.txt meanwhile
+        fs::write(&a, "other").unwrap();
+        assert_eq!(undo_last_run(&journal_path, |_, _, _, _| {}).unwrap(), 1);
+        assert!(b.exists() && moved_a.exists());
+
+        // and once it is gone, the undo can be retried
+        fs::remove_file(&a).unwrap();
+        assert_eq!(undo_last_run(&journal_path, |_, _, _, _| {}).unwrap(), 0);
+        assert_eq!(fs::read_to_string(&a).unwrap(), "a");
+        assert!(!journal_path.exists());
+    }
+}
@@ -0,0 +1,64 @@
+//! Kondo, a simple file organizer that sparks joy.
+//!
+//! Organizing a directory goes through these steps:
+//!
+//! 1. [`Config::load`] reads the categories and options from `config.toml`,
+//!    which [`find_config`] looks for when no path is given.
+//! 2. [`collect_files`] lists the files in the source directory.
+//! 3. A [`Classifier`] picks the category of each file, and a [`Planner`]
+//!    turns that into a list of [`PlannedMove`]s, resolving name conflicts.
+//! 4. An [`Executor`] applies the planned moves and journals them, so that
+//!    [`undo_last_run`] can revert them later.
+//!
+//! [`scan_files`], [`Planner::plan_each`] and [`Executor::execute_all`] do
+//! the same as the steps above, but stream the files from one step to the
+//! next and work on several at once. [`organize`] puts them together.
+//!
+//! [`watch`] runs the same steps for files as they arrive, [`review`] lets
+//! the user edit the outcome of step 3 in the terminal, and
+//! [`find_duplicates`] reports files with identical content.
+
+mod classify;
+mod config;
+mod dupes;
+mod error;
+mod execute;
+mod init;
+mod interactive;
+mod journal;
+mod organize;
+mod parallel;
+mod photo;
+mod plan;
+mod report;
+mod rules;
+mod scan;
+mod sniff;
+mod tags;
+mod template;
+mod transfer;
+mod tui;
+mod watch;
+
+pub use classify::{classify_files, Classification, Classifier};
+pub use config::{
+    config_search_paths, default_config_path, expand_path, find_config, Category, Config, Options, Profile,
+};
+pub use dupes::find_duplicates;
+pub use error::{KondoError, EXIT_CONFIG_ERROR, EXIT_ERROR, EXIT_INCOMPLETE};
+pub use execute::{Executor, Outcome};
+pub use init::starter_config;
+pub use interactive::{
+    has_config_changes, organize_interactively, save_answers, Answer, Choice, InteractiveMode, Prompter, Remembered,
+};
+pub use journal::{default_journal_path, undo_last_run};
+pub use organize::{organize, organize_paths, Progress};
+pub use parallel::MAX_JOBS;
+pub use plan::{Action, Chosen, ConflictPolicy, DuplicateAction, Plan, PlanItem, PlannedMove, Planner};
+pub use report::{FileRecord, OutputFormat, RecordAction, Reporter, Summary};
+pub use rules::{Conditions, Rule};
+pub use scan::{collect_files, scan_files, ScanOptions, IGNORE_FILE};
+pub use template::DateSource;
+pub use transfer::TransferMode;
+pub use tui::{review, Entry};
+pub use watch::{watch, WatchEvent, WatchOptions};
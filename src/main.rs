@@ -1,116 +1,875 @@
-use clap::Parser;
-use std::collections::HashMap;
+use clap::builder::{PossibleValuesParser, RangedU64ValueParser, TypedValueParser};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use kondo::{
+    classify_files, collect_files, config_search_paths, default_config_path, default_journal_path, find_config,
+    find_duplicates, organize, organize_interactively, organize_paths, review, starter_config, undo_last_run, watch,
+    Action, Classifier, Config, ConflictPolicy, DuplicateAction, Entry, Executor, FileRecord, InteractiveMode,
+    KondoError, Outcome, OutputFormat, PlannedMove, Planner, Profile, Progress, Prompter, Reporter, ScanOptions,
+    TransferMode, WatchEvent, WatchOptions, MAX_JOBS,
+};
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
-use toml::Value;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "File organizer by type")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    organize: OrganizeArgs,
+
+    /// Path to the journal of the last run [default: $XDG_STATE_HOME/kondo/journal.toml]
+    #[arg(long, global = true)]
+    journal: Option<String>,
+}
+
+// Options shared by a one-off run and by `kondo watch`
+#[derive(clap::Args, Debug)]
+struct OrganizeArgs {
     /// Source directory to scan
+    #[arg(short, long, required = true)]
+    source: Option<String>,
+
+    /// Path to the config.toml file [default: the first found of
+    /// $XDG_CONFIG_HOME/kondo/config.toml, ~/.config/kondo/config.toml and /etc/kondo/config.toml]
     #[arg(short, long)]
-    source: String,
+    config: Option<String>,
+
+    /// Print the planned moves without touching the disk
+    #[arg(long)]
+    dry_run: bool,
 
-    /// Path to the config.toml file
+    /// Also organize files in subdirectories of the source
     #[arg(short, long)]
-    config: String,
-}
+    recursive: bool,
 
-// Define file type mappings
-fn get_file_type_mappings() -> HashMap<Vec<&'static str>, &'static str> {
-    let mut mappings = HashMap::new();
+    /// How many levels of subdirectories to descend into [default: unlimited]
+    #[arg(long, requires = "recursive")]
+    max_depth: Option<usize>,
 
-    // Images
-    mappings.insert(vec!["jpg", "jpeg", "png", "gif", "bmp", "tiff", "webp"], "Images");
+    /// Skip files and directories matching this glob, e.g. `node_modules` or `*.app`
+    /// (in addition to the `exclude` option in the config)
+    #[arg(short, long, value_name = "GLOB")]
+    exclude: Vec<String>,
 
-    // Documents
-    mappings.insert(vec!["pdf", "doc", "docx", "txt", "rtf", "odt", "xls", "xlsx", "ppt", "pptx"], "Documents");
+    /// Detect file types from their content rather than trusting the extension alone
+    /// (also enabled by `sniff = true` in the config)
+    #[arg(long)]
+    sniff: bool,
 
-    // Audio
-    mappings.insert(vec!["mp3", "wav", "ogg", "flac", "aac", "wma"], "Audio");
+    /// What to do when a file of the same name already exists at the destination
+    /// [default: the `on_conflict` option in the config, or rename]
+    #[arg(long, value_parser = conflict_policy_parser())]
+    on_conflict: Option<ConflictPolicy>,
 
-    mappings
+    /// What to do with files identical to one already at the destination or
+    /// to another file of the run [default: the `on_duplicate` option in the config]
+    #[arg(long, value_parser = duplicate_action_parser())]
+    on_duplicate: Option<DuplicateAction>,
+
+    /// Move, copy or link files to their destination, for every category
+    /// [default: the `mode` options in the config, or move]
+    #[arg(long, value_parser = transfer_mode_parser())]
+    mode: Option<TransferMode>,
+
+    /// Organize files even if `.kondoignore` or the `ignore` option match them
+    #[arg(long)]
+    no_ignore: bool,
+
+    /// How many files to classify and transfer at once
+    /// [default: the `jobs` option in the config, or one per CPU]
+    #[arg(short, long, value_parser = RangedU64ValueParser::<usize>::new().range(1..=MAX_JOBS as u64))]
+    jobs: Option<usize>,
+
+    /// How to report the run: text for people, or json or ndjson (one
+    /// object per line) for scripts
+    #[arg(long, value_name = "FORMAT", default_value = "text", value_parser = output_format_parser())]
+    output: OutputFormat,
+
+    /// Ask before each move, or with `category` before each category's
+    /// moves, whether to go ahead, skip, or use another category
+    #[arg(
+        long,
+        value_name = "BY",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "file",
+        conflicts_with = "dry_run",
+        value_parser = interactive_mode_parser()
+    )]
+    interactive: Option<InteractiveMode>,
 }
 
-fn move_file(source: &Path, destination: &Path) -> std::io::Result<()> {
-    // Create destination directory if it doesn't exist
-    fs::create_dir_all(destination)?;
+impl OrganizeArgs {
+    // Required by clap unless a subcommand is given
+    fn source_dir(&self) -> PathBuf {
+        PathBuf::from(self.source.as_deref().unwrap_or_default())
+    }
 
-    if let Some(file_name) = source.file_name() {
-        let dest_path = destination.join(file_name);
-        fs::rename(source, dest_path)?;
-        println!("Moved: {} -> {}", source.display(), destination.display());
+    fn config_path(&self) -> Result<PathBuf, KondoError> {
+        config_path(self.config.as_deref())
     }
 
-    Ok(())
+    fn max_depth(&self) -> usize {
+        if self.recursive {
+            self.max_depth.unwrap_or(usize::MAX)
+        } else {
+            0
+        }
+    }
 }
 
-fn get_extension(file_path: &Path) -> Option<String> {
-    file_path
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .map(|s| s.to_lowercase())
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Organize the sources of profiles from the config, each with its own
+    /// categories and options
+    #[command(mut_arg("source", |arg| {
+        arg.required(false).help("Organize this directory instead of the sources of the profiles")
+    }))]
+    Run {
+        /// Profiles to run, in order
+        #[arg(value_name = "PROFILE", required_unless_present = "all")]
+        profiles: Vec<String>,
+
+        /// Run every profile of the config, in the order of their names
+        #[arg(long, conflicts_with = "profiles")]
+        all: bool,
+
+        #[command(flatten)]
+        organize: OrganizeArgs,
+    },
+    /// Revert the moves made by the last organize run
+    Undo,
+    /// Keep organizing files as they arrive in the source directory
+    Watch {
+        #[command(flatten)]
+        organize: OrganizeArgs,
+
+        /// Seconds a new file must stay unchanged before it is organized
+        #[arg(long, default_value_t = 2.0)]
+        settle: f64,
+    },
+    /// Review the files of the source directory in the terminal, grouped by
+    /// category, and re-categorise, exclude or rename them before organizing
+    Tui {
+        #[command(flatten)]
+        organize: OrganizeArgs,
+    },
+    /// List groups of files with identical content, without touching them
+    Dupes {
+        /// Directories to look in
+        #[arg(required = true)]
+        dirs: Vec<String>,
+
+        /// Also look in subdirectories
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Skip files and directories matching this glob
+        #[arg(short, long, value_name = "GLOB")]
+        exclude: Vec<String>,
+    },
+    /// Write a commented starter config, with destinations from the XDG
+    /// user directories
+    Init {
+        /// Where to write it [default: $XDG_CONFIG_HOME/kondo/config.toml]
+        #[arg(short, long)]
+        config: Option<String>,
+
+        /// Replace the file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Work with the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
 }
 
-fn organize_files(args: Args) -> std::io::Result<()> {
-    let source_dir = PathBuf::from(args.source);
-    let config_path = PathBuf::from(args.config);
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Check a config file without organizing anything
+    Validate {
+        /// Path to the config.toml file [default: the first found, as when organizing]
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+}
 
-    // Read and parse the config.toml file
-    let config_content = fs::read_to_string(config_path)?;
-    let config: Value = config_content.parse::<Value>()
-        .expect("Failed to parse the config file");
+fn conflict_policy_parser() -> impl TypedValueParser<Value = ConflictPolicy> {
+    PossibleValuesParser::new(ConflictPolicy::NAMES)
+        .map(|policy| policy.parse::<ConflictPolicy>().expect("policy names parse"))
+}
 
-    // Extract output directories from the config
-    let directories = config.get("directories").expect("Missing 'directories' section in config");
-    let images_dir = PathBuf::from(directories.get("images").expect("Missing 'images' key in config").as_str().unwrap());
-    let documents_dir = PathBuf::from(directories.get("documents").expect("Missing 'documents' key in config").as_str().unwrap());
-    let audio_dir = PathBuf::from(directories.get("audio").expect("Missing 'audio' key in config").as_str().unwrap());
+fn duplicate_action_parser() -> impl TypedValueParser<Value = DuplicateAction> {
+    PossibleValuesParser::new(DuplicateAction::NAMES)
+        .map(|action| action.parse::<DuplicateAction>().expect("duplicate action names parse"))
+}
 
-    let mappings = get_file_type_mappings();
+fn transfer_mode_parser() -> impl TypedValueParser<Value = TransferMode> {
+    PossibleValuesParser::new(TransferMode::NAMES)
+        .map(|mode| mode.parse::<TransferMode>().expect("transfer mode names parse"))
+}
 
-    // Check if source directory exists
-    if !source_dir.exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("Source directory does not exist: {}", source_dir.display()),
-        ));
+fn output_format_parser() -> impl TypedValueParser<Value = OutputFormat> {
+    PossibleValuesParser::new(OutputFormat::NAMES)
+        .map(|format| format.parse::<OutputFormat>().expect("output format names parse"))
+}
+
+fn interactive_mode_parser() -> impl TypedValueParser<Value = InteractiveMode> {
+    PossibleValuesParser::new(InteractiveMode::NAMES)
+        .map(|mode| mode.parse::<InteractiveMode>().expect("interactive mode names parse"))
+}
+
+// The config given on the command line, or else the first one found
+fn config_path(config: Option<&str>) -> Result<PathBuf, KondoError> {
+    match config {
+        Some(config) => Ok(PathBuf::from(config)),
+        None => find_config(),
     }
+}
 
-    for entry in fs::read_dir(source_dir)? {
-        let entry = entry?;
-        let path = entry.path();
+fn journal_path(args: &Args) -> PathBuf {
+    args.journal.as_ref().map(PathBuf::from).unwrap_or_else(default_journal_path)
+}
 
-        // Skip directories
-        if path.is_dir() {
+fn print_plan(plan: &[PlannedMove]) {
+    // Report every directory that would have to be created, once
+    let mut new_dirs: Vec<&Path> = Vec::new();
+    for planned in plan {
+        if !matches!(planned.action, Action::Move | Action::Overwrite) {
             continue;
         }
 
-        if let Some(extension) = get_extension(&path) {
-            // Find matching category for the file extension
-            for (extensions, category) in &mappings {
-                if extensions.iter().any(|&ext| ext == extension) {
-                    let dest_dir = match *category {
-                        "Images" => &images_dir,
-                        "Documents" => &documents_dir,
-                        "Audio" => &audio_dir,
-                        _ => continue,
-                    };
-                    move_file(&path, dest_dir)?;
-                    break;
+        if let Some(dir) = planned.destination.parent() {
+            if !dir.exists() && !new_dirs.contains(&dir) {
+                new_dirs.push(dir);
+            }
+        }
+    }
+
+    for dir in &new_dirs {
+        println!("Would create: {}", dir.display());
+    }
+
+    let mut moved = 0;
+    for planned in plan {
+        let (source, destination) = (planned.source.display(), planned.destination.display());
+        let verb = planned.mode.verb();
+        match &planned.action {
+            Action::Move if planned.duplicate_of.is_some() => {
+                println!("Would move duplicate: {} -> {}", source, destination)
+            }
+            Action::Move => println!("Would {}: {} -> {}", verb, source, destination),
+            Action::Overwrite if planned.mode == TransferMode::Move => {
+                println!("Would overwrite: {} -> {}", source, destination)
+            }
+            Action::Overwrite => println!("Would overwrite ({}): {} -> {}", verb, source, destination),
+            Action::Skip(reason) => println!("Would skip: {} ({}: {})", source, reason, destination),
+            Action::Delete => println!("Would delete duplicate: {} (same as {})", source, destination),
+            Action::Hardlink => println!("Would link duplicate: {} -> {}", source, destination),
+        }
+
+        if matches!(planned.action, Action::Move | Action::Overwrite) {
+            moved += 1;
+        }
+    }
+
+    println!("Dry run: {} file(s) would be organized, nothing was changed", moved);
+}
+
+// `copied` becomes `Copied`
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+fn print_config_warnings(config_path: &Path, config: &Config) {
+    for warning in &config.warnings {
+        eprintln!("Warning: {}: {}", config_path.display(), warning);
+    }
+}
+
+// Print every per-file failure of a run once it is over
+fn print_failures(failures: &[KondoError]) {
+    if failures.is_empty() {
+        return;
+    }
+
+    eprintln!("\n{} failure(s):", failures.len());
+    for failure in failures {
+        eprintln!("  {}", failure);
+    }
+}
+
+// Load the config and apply the command line options, which take precedence
+// over the config and its profiles alike
+fn load_config(args: &OrganizeArgs) -> Result<Config, KondoError> {
+    let config_path = args.config_path()?;
+    let mut config = Config::load(&config_path)?;
+    print_config_warnings(&config_path, &config);
+
+    apply_args(args, &mut config);
+    for profile in &mut config.profiles {
+        apply_args(args, &mut profile.config);
+    }
+    Ok(config)
+}
+
+fn apply_args(args: &OrganizeArgs, config: &mut Config) {
+    if let Some(policy) = args.on_conflict {
+        config.options.on_conflict = policy;
+    }
+    config.options.exclude.extend(args.exclude.iter().cloned());
+    config.options.sniff |= args.sniff;
+    // A mode on the command line is meant for everything, categories included
+    if let Some(mode) = args.mode {
+        config.options.mode = mode;
+        for category in &mut config.categories {
+            category.mode = None;
+        }
+    }
+    if args.on_duplicate.is_some() {
+        config.options.on_duplicate = args.on_duplicate;
+    }
+    if let Some(jobs) = args.jobs {
+        config.options.jobs = jobs;
+    }
+}
+
+fn check_source_dir(source_dir: &Path) -> Result<(), KondoError> {
+    if source_dir.is_dir() {
+        return Ok(());
+    }
+
+    Err(KondoError::io(
+        source_dir,
+        std::io::Error::new(std::io::ErrorKind::NotFound, "source directory does not exist"),
+    ))
+}
+
+// Where the outcome of each file goes: lines for people, or the records of
+// `--output json` and `ndjson`
+enum Output {
+    Text,
+    Records(Reporter<io::Stdout>),
+}
+
+impl Output {
+    fn new(args: &OrganizeArgs) -> Output {
+        match args.output {
+            OutputFormat::Text => Output::Text,
+            format => Output::Records(Reporter::new(io::stdout(), format, args.dry_run)),
+        }
+    }
+
+    // Report a planned move once carried out. Failures are only recorded
+    // right away; as text they are listed once the run is over.
+    fn executed(
+        &mut self,
+        planned: &PlannedMove,
+        outcome: Outcome,
+        failures: &mut Vec<KondoError>,
+    ) -> Result<(), KondoError> {
+        match self {
+            Output::Text => print_outcome(planned, outcome, failures),
+            Output::Records(reporter) => {
+                reporter.record(FileRecord::executed(planned, &outcome)).map_err(stdout_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Report what the organizing says about a file. Warnings go to standard
+    // error, and failures are kept for the end of the run.
+    fn progress(&mut self, progress: Progress, failures: &mut Vec<KondoError>) -> Result<(), KondoError> {
+        match progress {
+            Progress::Executed(planned, outcome) => return self.executed(planned, outcome, failures),
+            Progress::Warning(warning) => eprintln!("Warning: {}", warning),
+            Progress::Failure(e) => failures.push(e),
+        }
+        Ok(())
+    }
+
+    fn planned(&mut self, moves: &[PlannedMove]) -> Result<(), KondoError> {
+        match self {
+            Output::Text => print_plan(moves),
+            Output::Records(reporter) => {
+                for planned in moves {
+                    reporter.record(FileRecord::planned(planned)).map_err(stdout_error)?;
                 }
             }
         }
+        Ok(())
     }
 
+    fn failures(&mut self, failures: &[KondoError]) -> Result<(), KondoError> {
+        match self {
+            Output::Text => print_failures(failures),
+            Output::Records(reporter) => {
+                for failure in failures {
+                    reporter.record(FileRecord::failed(failure)).map_err(stdout_error)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Report the failures left and end the run, returning how many files failed
+    fn finish(&mut self, failures: &[KondoError]) -> Result<usize, KondoError> {
+        self.failures(failures)?;
+        match self {
+            Output::Text => Ok(failures.len()),
+            Output::Records(reporter) => {
+                reporter.finish().map_err(stdout_error)?;
+                Ok(reporter.summary().failed)
+            }
+        }
+    }
+}
+
+fn stdout_error(e: io::Error) -> KondoError {
+    KondoError::io("standard output", e)
+}
+
+// With --dry-run, plan the files and print the plan, changing nothing
+fn print_dry_run(
+    config: &Config,
+    planner: &mut Planner,
+    paths: Vec<PathBuf>,
+    output: &mut Output,
+    failures: &mut Vec<KondoError>,
+) -> Result<(), KondoError> {
+    let plan = planner.plan(&Classifier::new(config), paths);
+    for warning in &plan.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    failures.extend(plan.failures);
+    output.planned(&plan.moves)
+}
+
+fn print_outcome(planned: &PlannedMove, outcome: Outcome, failures: &mut Vec<KondoError>) {
+    let (source, destination) = (planned.source.display(), planned.destination.display());
+    match outcome {
+        Outcome::Transferred if planned.duplicate_of.is_some() => {
+            println!("Moved duplicate: {} -> {}", source, destination)
+        }
+        Outcome::Transferred => {
+            println!("{}: {} -> {}", capitalize(planned.mode.past_tense()), source, destination)
+        }
+        Outcome::Deleted => println!("Deleted duplicate: {} (same as {})", source, destination),
+        Outcome::Linked => println!("Linked duplicate: {} -> {}", source, destination),
+        Outcome::Skipped(reason) => println!("Skipped: {} ({}: {})", source, reason, destination),
+        Outcome::Failed(e) => failures.push(e),
+    }
+}
+
+fn organize_files(args: &OrganizeArgs, journal_path: PathBuf) -> Result<(), KondoError> {
+    let source_dir = args.source_dir();
+    let config = load_config(args)?;
+    check_source_dir(&source_dir)?;
+
+    let executor = Executor::new(journal_path);
+    if let Some(mode) = args.interactive {
+        if args.output != OutputFormat::Text {
+            Args::command()
+                .error(ErrorKind::ArgumentConflict, "`--interactive` needs the text output")
+                .exit();
+        }
+
+        let mut failures = Vec::new();
+        let paths = collect_files(&source_dir, &scan_options(args, &config, &source_dir)?, &mut failures)?;
+        let mut prompter = Prompter::new(io::stdin().lock(), io::stdout());
+        let mut outcome_failures = Vec::new();
+        failures.extend(organize_interactively(
+            paths,
+            &config,
+            &args.config_path()?,
+            &executor,
+            mode,
+            &mut prompter,
+            |planned, outcome| print_outcome(planned, outcome, &mut outcome_failures),
+        )?);
+        failures.extend(outcome_failures);
+        return finish_run(&mut Output::Text, &failures);
+    }
+
+    let mut output = Output::new(args);
+    let mut planner = Planner::new(&config.options);
+    let failures = organize_source(args, &config, &source_dir, &mut planner, &executor, &mut output)?;
+    finish_run(&mut output, &failures)
+}
+
+// What to scan in `source_dir`, which has a say unless --no-ignore is given
+fn scan_options(args: &OrganizeArgs, config: &Config, source_dir: &Path) -> Result<ScanOptions, KondoError> {
+    let mut scan_options = ScanOptions::new(config, args.max_depth())?;
+    if !args.no_ignore {
+        scan_options.read_ignores(source_dir)?;
+    }
+    Ok(scan_options)
+}
+
+// Organize (or with --dry-run, print) the files of a source directory while
+// it is scanned. Problems with single files are returned rather than
+// aborting the run halfway.
+fn organize_source(
+    args: &OrganizeArgs,
+    config: &Config,
+    source_dir: &Path,
+    planner: &mut Planner,
+    executor: &Executor,
+    output: &mut Output,
+) -> Result<Vec<KondoError>, KondoError> {
+    let scan_options = scan_options(args, config, source_dir)?;
+
+    let mut failures = Vec::new();
+    if args.dry_run {
+        let paths = collect_files(source_dir, &scan_options, &mut failures)?;
+        print_dry_run(config, planner, paths, output, &mut failures)?;
+    } else {
+        organize(source_dir, &scan_options, config, planner, executor, |progress| {
+            output.progress(progress, &mut failures)
+        })?;
+    }
+    Ok(failures)
+}
+
+// Report the failures and end the output, failing if any file did
+fn finish_run(output: &mut Output, failures: &[KondoError]) -> Result<(), KondoError> {
+    let failed = output.finish(failures)?;
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(KondoError::Incomplete { failed, action: "organized" })
+    }
+}
+
+// Organize the sources of the named profiles, or of all of them, one after
+// the other. The whole run shares one journal, so `kondo undo` reverts all of
+// it, and one planner, so that --dry-run sees the conflicts between sources.
+fn run_profiles(names: &[String], all: bool, args: &OrganizeArgs, journal_path: PathBuf) -> Result<(), KondoError> {
+    if args.interactive.is_some() {
+        Args::command()
+            .error(ErrorKind::ArgumentConflict, "`run` cannot ask about each file, use `--interactive` on one source")
+            .exit();
+    }
+
+    let config = load_config(args)?;
+    let config_path = args.config_path()?;
+    let config_error = |message: String| KondoError::ConfigFile { path: config_path.clone(), message };
+
+    let profiles: Vec<&Profile> = if all {
+        if config.profiles.is_empty() {
+            return Err(config_error("no profiles to run".to_string()));
+        }
+        config.profiles.iter().collect()
+    } else {
+        let mut profiles = Vec::new();
+        for name in names {
+            let profile = config.profile(name).ok_or_else(|| {
+                let known: Vec<&str> = config.profiles.iter().map(|profile| profile.name.as_str()).collect();
+                config_error(format!("no profile named '{}' (the profiles are: {})", name, known.join(", ")))
+            })?;
+            profiles.push(profile);
+        }
+        profiles
+    };
+
+    let executor = Executor::new(journal_path);
+    let mut output = Output::new(args);
+    let mut planner = Planner::new(&config.options);
+    let mut failures = Vec::new();
+    let mut missing_sources = Vec::new();
+    for profile in profiles {
+        planner.set_options(&profile.config.options);
+        let sources = match &args.source {
+            Some(source) => vec![PathBuf::from(source)],
+            None => profile.sources.clone(),
+        };
+
+        for source_dir in &sources {
+            if matches!(output, Output::Text) {
+                println!("Profile {}: {}", profile.name, source_dir.display());
+            }
+            // A missing source is no reason to leave the others alone
+            if let Err(e) = check_source_dir(source_dir) {
+                missing_sources.push(e);
+                continue;
+            }
+            let config = &profile.config;
+            failures.extend(organize_source(args, config, source_dir, &mut planner, &executor, &mut output)?);
+        }
+    }
+
+    // A missing source is an error of the run rather than of its files, so
+    // it is what the run ends with
+    let finished = finish_run(&mut output, &failures);
+    let Some(missing_source) = missing_sources.pop() else {
+        return finished;
+    };
+    for e in missing_sources.iter().chain(finished.as_ref().err()) {
+        eprintln!("Error: {}", e);
+    }
+    Err(missing_source)
+}
+
+fn watch_files(args: &OrganizeArgs, settle: f64, journal_path: PathBuf) -> Result<(), KondoError> {
+    if args.output == OutputFormat::Json {
+        Args::command()
+            .error(ErrorKind::ArgumentConflict, "`watch` never ends a JSON document, use `--output ndjson`")
+            .exit();
+    }
+    if args.interactive.is_some() {
+        Args::command().error(ErrorKind::ArgumentConflict, "`watch` cannot ask about each file").exit();
+    }
+
+    let source_dir = args.source_dir();
+    let config = load_config(args)?;
+    check_source_dir(&source_dir)?;
+
+    // The watcher reports canonical paths
+    let source_dir = fs::canonicalize(&source_dir).map_err(|e| KondoError::io(&source_dir, e))?;
+
+    let scan_options = scan_options(args, &config, &source_dir)?;
+    let options = WatchOptions {
+        recursive: args.recursive,
+        settle: Duration::from_secs_f64(settle.max(0.0)),
+    };
+
+    // The whole session shares one journal, so `kondo undo` reverts all of it
+    let executor = Executor::new(journal_path);
+    let mut output = Output::new(args);
+
+    // Keep standard output for the records
+    match output {
+        Output::Text => println!("Watching {} (press Ctrl-C to stop)", source_dir.display()),
+        Output::Records(_) => eprintln!("Watching {} (press Ctrl-C to stop)", source_dir.display()),
+    }
+    watch(&source_dir, &options, |event| {
+        let mut paths = match event {
+            WatchEvent::Ready(paths) => paths,
+            WatchEvent::Warning(warning) => {
+                eprintln!("Warning: {}", warning);
+                return Ok(());
+            }
+            WatchEvent::SourceGone => {
+                eprintln!("Source directory {} is gone, waiting for it to come back", source_dir.display());
+                return Ok(());
+            }
+            WatchEvent::SourceBack => {
+                eprintln!("Watching {} again", source_dir.display());
+                return Ok(());
+            }
+        };
+        paths.retain(|path| !scan_options.is_excluded(&source_dir, path));
+        paths.sort();
+
+        let mut failures = Vec::new();
+        let mut planner = Planner::new(&config.options);
+        if args.dry_run {
+            print_dry_run(&config, &mut planner, paths, &mut output, &mut failures)?;
+        } else {
+            organize_paths(paths.into_iter(), &config, &mut planner, &executor, |progress| {
+                output.progress(progress, &mut failures)
+            })?;
+        }
+        match &mut output {
+            Output::Text => {
+                for failure in &failures {
+                    eprintln!("Failed: {}", failure);
+                }
+            }
+            Output::Records(_) => output.failures(&failures)?,
+        }
+        Ok(())
+    })
+}
+
+// Classify every file, let the user review and edit the plan in the terminal,
+// then organize (or with --dry-run, print) the files as edited
+fn review_files(args: &OrganizeArgs, journal_path: PathBuf) -> Result<(), KondoError> {
+    if args.interactive.is_some() {
+        Args::command().error(ErrorKind::ArgumentConflict, "`tui` already lets you review each file").exit();
+    }
+
+    let source_dir = args.source_dir();
+    let config = load_config(args)?;
+    check_source_dir(&source_dir)?;
+
+    let scan_options = scan_options(args, &config, &source_dir)?;
+    let mut failures = Vec::new();
+    let paths = collect_files(&source_dir, &scan_options, &mut failures)?;
+    let mut warnings = Vec::new();
+    let entries = classify_files(&config, paths, &mut warnings, &mut failures)
+        .into_iter()
+        .map(|(path, category)| Entry::new(path, category))
+        .collect();
+    for warning in &warnings {
+        eprintln!("Warning: {}", warning);
+    }
+
+    let Some(chosen) = review(&config, &source_dir, entries).map_err(|e| KondoError::io("the terminal", e))? else {
+        println!("Nothing was changed");
+        return Ok(());
+    };
+
+    // Planned again rather than taken from the preview, in case the files
+    // changed in the meantime
+    let mut moves = Vec::new();
+    for planned in Planner::new(&config.options).plan_chosen(chosen) {
+        match planned {
+            Ok(planned) => moves.push(planned),
+            Err(e) => failures.push(e),
+        }
+    }
+
+    let mut output = Output::new(args);
+    if args.dry_run {
+        output.planned(&moves)?;
+    } else {
+        let executor = Executor::new(journal_path);
+        executor.execute_all(moves.into_iter(), config.options.jobs, |planned, outcome| {
+            output.executed(planned, outcome, &mut failures)
+        })?;
+    }
+
+    finish_run(&mut output, &failures)
+}
+
+fn undo(args: &Args) -> Result<(), KondoError> {
+    let journal_path = journal_path(args);
+
+    if !journal_path.exists() {
+        println!("Nothing to undo");
+        return Ok(());
+    }
+
+    let failed = undo_last_run(&journal_path, |current, original, mode, result| match (mode, result) {
+        (TransferMode::Move, Ok(())) => println!("Restored: {} -> {}", current.display(), original.display()),
+        (TransferMode::Move, Err(reason)) => eprintln!("Could not restore {}: {}", original.display(), reason),
+        (_, Ok(())) => println!("Removed {}: {}", mode.verb(), current.display()),
+        (_, Err(reason)) => eprintln!("Could not remove {}: {}", current.display(), reason),
+    })?;
+
+    if failed == 0 {
+        return Ok(());
+    }
+
+    eprintln!("The remaining moves are kept in {}", journal_path.display());
+    Err(KondoError::Incomplete { failed, action: "restored" })
+}
+
+fn report_duplicates(dirs: &[String], recursive: bool, exclude: &[String]) -> Result<(), KondoError> {
+    let mut config = Config::default();
+    config.options.exclude = exclude.to_vec();
+    let scan_options = ScanOptions::new(&config, if recursive { usize::MAX } else { 0 })?;
+
+    let mut failures = Vec::new();
+    let mut paths = Vec::new();
+    for dir in dirs {
+        let dir = Path::new(dir);
+        check_source_dir(dir)?;
+        paths.extend(collect_files(dir, &scan_options, &mut failures)?);
+    }
+
+    let groups = find_duplicates(&paths, &mut failures);
+    let mut wasted = 0;
+    for group in &groups {
+        let size = fs::metadata(&group[0]).map(|m| m.len()).unwrap_or(0);
+        wasted += size * (group.len() as u64 - 1);
+
+        println!("{} identical files of {} bytes:", group.len(), size);
+        for path in group {
+            println!("  {}", path.display());
+        }
+        println!();
+    }
+
+    if groups.is_empty() {
+        println!("No duplicates found");
+    } else {
+        println!("{} group(s) of duplicates, {} bytes could be freed", groups.len(), wasted);
+    }
+
+    print_failures(&failures);
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(KondoError::Incomplete { failed: failures.len(), action: "read" })
+    }
+}
+
+fn validate_config(config: Option<&str>) -> Result<(), KondoError> {
+    let config_path = &config_path(config)?;
+    let config = Config::load(config_path)?;
+    print_config_warnings(config_path, &config);
+
+    let count = |count: usize, one: &str, many: &str| format!("{} {}", count, if count == 1 { one } else { many });
+    let mut parts = vec![count(config.categories.len(), "category", "categories")];
+    if !config.rules.is_empty() {
+        parts.push(count(config.rules.len(), "rule", "rules"));
+    }
+    if !config.profiles.is_empty() {
+        parts.push(count(config.profiles.len(), "profile", "profiles"));
+    }
+
+    // `2 categories, 1 rule and 3 profiles`
+    let last = parts.pop().unwrap_or_default();
+    let summary = if parts.is_empty() { last } else { format!("{} and {}", parts.join(", "), last) };
+    println!("{} is valid, with {}", config_path.display(), summary);
+    Ok(())
+}
+
+fn init_config(config: Option<&str>, force: bool) -> Result<(), KondoError> {
+    let config_path = config.map(PathBuf::from).unwrap_or_else(default_config_path);
+    if config_path.exists() && !force {
+        let e = io::Error::new(io::ErrorKind::AlreadyExists, "already exists, pass --force to replace it");
+        return Err(KondoError::io(&config_path, e));
+    }
+
+    if let Some(dir) = config_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| KondoError::io(dir, e))?;
+    }
+    fs::write(&config_path, starter_config()).map_err(|e| KondoError::io(&config_path, e))?;
+
+    println!("Wrote {}", config_path.display());
+    // Only a config in one of these places is found without --config
+    let absolute = std::path::absolute(&config_path).unwrap_or_else(|_| config_path.clone());
+    if !config_search_paths().contains(&absolute) {
+        println!("Pass --config {} to use it", config_path.display());
+    }
+    println!("Check the destinations, then try `kondo run downloads --dry-run`");
     Ok(())
 }
 
 fn main() {
     let args = Args::parse();
 
-    if let Err(e) = organize_files(args) {
+    let result = match args.command {
+        Some(Command::Run { ref profiles, all, ref organize }) => {
+            run_profiles(profiles, all, organize, journal_path(&args))
+        }
+        Some(Command::Undo) => undo(&args),
+        Some(Command::Watch { ref organize, settle }) => watch_files(organize, settle, journal_path(&args)),
+        Some(Command::Tui { ref organize }) => review_files(organize, journal_path(&args)),
+        Some(Command::Dupes { ref dirs, recursive, ref exclude }) => report_duplicates(dirs, recursive, exclude),
+        Some(Command::Init { ref config, force }) => init_config(config.as_deref(), force),
+        Some(Command::Config { command: ConfigCommand::Validate { ref config } }) => validate_config(config.as_deref()),
+        None => organize_files(&args.organize, journal_path(&args)),
+    };
+
+    if let Err(e) = result {
         eprintln!("Error: {}", e);
-        std::process::exit(1);
+        std::process::exit(e.exit_code());
     }
 }
@@ -0,0 +1,126 @@
+use crate::classify::Classifier;
+use crate::config::Config;
+use crate::error::KondoError;
+use crate::execute::{Executor, Outcome};
+use crate::plan::{PlanItem, PlannedMove, Planner};
+use crate::scan::{scan_files, ScanOptions};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+/// What [`organize`] and [`organize_paths`] report about the files
+#[derive(Debug)]
+pub enum Progress<'a> {
+    /// A planned move, once carried out
+    Executed(&'a PlannedMove, Outcome),
+    /// A non-fatal remark, such as content not matching the extension
+    Warning(String),
+    /// A file that could not be scanned, classified or planned
+    Failure(KondoError),
+}
+
+/// Organize the files of `source_dir` while it is scanned: each file is
+/// classified, planned and moved as soon as it is found, while the next ones
+/// are still being looked for. Problems with single files are reported rather
+/// than aborting the run halfway. Stops at the first error from `report`.
+pub fn organize(
+    source_dir: &Path,
+    scan_options: &ScanOptions,
+    config: &Config,
+    planner: &mut Planner,
+    executor: &Executor,
+    mut report: impl FnMut(Progress) -> Result<(), KondoError> + Send,
+) -> Result<(), KondoError> {
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        let scanning = scope.spawn(move || {
+            let mut failures = Vec::new();
+            scan_files(source_dir, scan_options, &mut failures, |path| {
+                let _ = sender.send(path);
+            })?;
+            Ok::<_, KondoError>(failures)
+        });
+
+        let organized = organize_paths(receiver.into_iter(), config, planner, executor, &mut report);
+
+        let failures = scanning.join().expect("the scan panicked")?;
+        organized?;
+        failures.into_iter().try_for_each(|failure| report(Progress::Failure(failure)))
+    })
+}
+
+/// Classify, plan and move the given files. Each file is moved as soon as it
+/// is planned, while later ones are still being classified, and the moves are
+/// reported in the order of `paths`. Stops at the first error from `report`.
+pub fn organize_paths(
+    paths: impl Iterator<Item = PathBuf> + Send,
+    config: &Config,
+    planner: &mut Planner,
+    executor: &Executor,
+    report: impl FnMut(Progress) -> Result<(), KondoError> + Send,
+) -> Result<(), KondoError> {
+    let classifier = Classifier::new(config);
+    // Moves are reported by the executor while the planner reports the rest
+    let report = Mutex::new(report);
+    let report = |progress: Progress| (report.lock().unwrap_or_else(|e| e.into_inner()))(progress);
+
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        let executing = scope.spawn(|| {
+            executor.execute_all(receiver.into_iter(), config.options.jobs, |planned, outcome| {
+                report(Progress::Executed(planned, outcome))
+            })
+        });
+
+        // Sending only fails once the executor has stopped, and its error
+        // is the one to report
+        let planned = planner.plan_each(&classifier, paths, |item| match item {
+            PlanItem::Move(planned) => sender.send(planned).map_err(|_| None),
+            PlanItem::Warning(warning) => report(Progress::Warning(warning)).map_err(Some),
+            PlanItem::Failure(e) => report(Progress::Failure(e)).map_err(Some),
+        });
+        drop(sender);
+
+        executing.join().expect("the executor panicked")?;
+        match planned {
+            Err(Some(e)) => Err(e),
+            _ => Ok(()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn files_are_organized_while_the_source_is_scanned() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("src");
+        fs::create_dir_all(source.join("sub")).unwrap();
+        for name in ["a.txt", "b.txt", "sub/c.txt", "d.bin"] {
+            fs::write(source.join(name), name).unwrap();
+        }
+        let text = "[categories.documents]\nextensions = [\"txt\"]\n\
+                    destination = \"src/Documents\"\n";
+        let config = Config::parse(text, dir.path()).unwrap();
+        let scan_options = ScanOptions::new(&config, usize::MAX).unwrap();
+        let mut planner = Planner::new(&config.options);
+        let executor = Executor::new(dir.path().join("journal.toml"));
+
+        let mut moved = Vec::new();
+        organize(&source, &scan_options, &config, &mut planner, &executor, |progress| {
+            match progress {
+                Progress::Executed(planned, Outcome::Transferred) => moved.push(planned.source.clone()),
+                other => panic!("unexpected {:?}", other),
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(moved, [source.join("a.txt"), source.join("b.txt"), source.join("sub/c.txt")]);
+        assert!(source.join("Documents/c.txt").exists());
+        assert!(source.join("d.bin").exists());
+    }
+}
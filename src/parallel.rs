@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+
+/// The most workers a run may use, as each one is a thread of its own
+pub const MAX_JOBS: usize = 1024;
+
+/// How many workers to use when the config does not say: one per CPU
+pub(crate) fn default_jobs() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1).min(MAX_JOBS)
+}
+
+/// Apply `work` to every item on up to `jobs` threads, and hand the results
+/// to `deliver` on the calling thread, in the order of the items. Items are
+/// taken one at a time, so `items` may still be filled by another thread
+/// while the first ones are worked on, and each item is only taken once all
+/// the items before it have been.
+///
+/// An error from `deliver` stops the workers from taking more items, and is
+/// returned once those in progress are done.
+pub(crate) fn parallel_map<T, R, E>(
+    items: impl Iterator<Item = T> + Send,
+    jobs: usize,
+    work: impl Fn(T) -> R + Sync,
+    mut deliver: impl FnMut(R) -> Result<(), E>,
+) -> Result<(), E>
+where
+    T: Send,
+    R: Send,
+{
+    let items = Mutex::new(items.enumerate());
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.max(1) {
+            let (items, stop, work, sender) = (&items, &stop, &work, sender.clone());
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    // The lock is released before the work starts
+                    let next = items.lock().unwrap_or_else(|e| e.into_inner()).next();
+                    let Some((index, item)) = next else {
+                        break;
+                    };
+                    if sender.send((index, work(item))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // Results finished out of order wait here for those before them
+        let mut finished = BTreeMap::new();
+        let mut next = 0;
+        let mut result = Ok(());
+        for (index, output) in receiver {
+            finished.insert(index, output);
+            while let Some(output) = finished.remove(&next) {
+                next += 1;
+                if result.is_ok() {
+                    result = deliver(output);
+                    if result.is_err() {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+        result
+    })
+}
+
+/// Which items of a `parallel_map` are done, for items that must wait for
+/// an earlier one
+#[derive(Default)]
+pub(crate) struct Completion {
+    done: Mutex<Vec<bool>>,
+    changed: Condvar,
+}
+
+impl Completion {
+    pub(crate) fn finish(&self, index: usize) {
+        let mut done = self.done.lock().unwrap_or_else(|e| e.into_inner());
+        if done.len() <= index {
+            done.resize(index + 1, false);
+        }
+        done[index] = true;
+        self.changed.notify_all();
+    }
+
+    /// Block until item `index` is done. It must have been taken already,
+    /// e.g. by coming before the item waiting for it.
+    pub(crate) fn wait_for(&self, index: usize) {
+        let mut done = self.done.lock().unwrap_or_else(|e| e.into_inner());
+        while !done.get(index).copied().unwrap_or(false) {
+            done = self.changed.wait(done).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use crate::sniff::sniff_file_type;
+use chrono::{NaiveDate, NaiveDateTime};
+use exif::{In, Reader, Tag, Value};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+// Formats that carry EXIF metadata, as reported by `sniff_file_type`
+const PHOTO_FORMATS: [&str; 4] = ["jpg", "tiff", "heic", "webp"];
+
+/// Whether the file's content is a photo format that can carry a capture date
+pub(crate) fn is_photo(path: &Path) -> io::Result<bool> {
+    Ok(sniff_file_type(path)?.is_some_and(|format| PHOTO_FORMATS.contains(&format)))
+}
+
+/// When the photo was taken according to its EXIF `DateTimeOriginal`, if it
+/// has a usable one. Unreadable or broken metadata counts as none.
+pub(crate) fn capture_date(path: &Path) -> Option<NaiveDateTime> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let exif = Reader::new().read_from_container(&mut reader).ok()?;
+    let field = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY)?;
+
+    let Value::Ascii(values) = &field.value else {
+        return None;
+    };
+    let taken = exif::DateTime::from_ascii(values.first()?).ok()?;
+
+    // Cameras without a set clock write `0000:00:00 00:00:00`
+    NaiveDate::from_ymd_opt(taken.year.into(), taken.month.into(), taken.day.into())?
+        .and_hms_opt(taken.hour.into(), taken.minute.into(), taken.second.into())
+}
@@ -0,0 +1,644 @@
+use crate::classify::{matching_extension, Classifier};
+use crate::config::{Category, Options};
+use crate::dupes::{is_same_file, DuplicateIndex};
+use crate::error::KondoError;
+use crate::parallel::parallel_map;
+use crate::template::{expand_name, expand_template, TemplateValues};
+use crate::transfer::{is_transferred, TransferMode};
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// What to do when a file of the same name already exists at the destination
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Leave the source file where it is
+    Skip,
+    /// Replace the existing file
+    Overwrite,
+    /// Add a numeric suffix, e.g. `photo (1).jpg`
+    Rename,
+    /// Add the modification time of the source file, e.g. `photo (20240131-154502).jpg`
+    Timestamp,
+    /// Keep whichever of the two files was modified last
+    Newer,
+    /// Keep whichever of the two files is larger
+    Larger,
+}
+
+impl ConflictPolicy {
+    pub const NAMES: [&'static str; 6] = ["skip", "overwrite", "rename", "timestamp", "newer", "larger"];
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ConflictPolicy, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "skip" => Ok(ConflictPolicy::Skip),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "rename" => Ok(ConflictPolicy::Rename),
+            "timestamp" => Ok(ConflictPolicy::Timestamp),
+            "newer" => Ok(ConflictPolicy::Newer),
+            "larger" => Ok(ConflictPolicy::Larger),
+            _ => Err(format!("unknown conflict policy '{}'", s)),
+        }
+    }
+}
+
+/// What to do with a file whose content is already at the destination, or
+/// in another file of the same run
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateAction {
+    /// Leave the source file where it is
+    Skip,
+    /// Delete the source file
+    Delete,
+    /// Move the source file to the duplicates folder instead
+    Move,
+    /// Replace the source file with a hard link to the identical one
+    Hardlink,
+}
+
+impl DuplicateAction {
+    pub const NAMES: [&'static str; 4] = ["skip", "delete", "move", "hardlink"];
+}
+
+impl FromStr for DuplicateAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DuplicateAction, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "skip" => Ok(DuplicateAction::Skip),
+            "delete" => Ok(DuplicateAction::Delete),
+            "move" => Ok(DuplicateAction::Move),
+            "hardlink" => Ok(DuplicateAction::Hardlink),
+            _ => Err(format!("unknown duplicate action '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Move,
+    /// Move, replacing the file already at the destination
+    Overwrite,
+    /// Leave the source alone, for the given reason
+    Skip(String),
+    /// Delete the source, a duplicate of the destination
+    Delete,
+    /// Replace the source with a hard link to the destination, which it duplicates
+    Hardlink,
+}
+
+/// A move decided during classification, carried out later (or only printed)
+#[derive(Clone, Debug)]
+pub struct PlannedMove {
+    pub source: PathBuf,
+    /// Full path of the file once moved
+    pub destination: PathBuf,
+    pub category: String,
+    pub action: Action,
+    /// Whether the file is moved, copied or linked
+    pub mode: TransferMode,
+    /// The identical file found for a duplicate, where it is or will be once moved
+    pub duplicate_of: Option<PathBuf>,
+    /// Size of the source in bytes when the move was planned
+    pub size: u64,
+}
+
+/// The outcome of planning a whole scan
+#[derive(Default)]
+pub struct Plan {
+    pub moves: Vec<PlannedMove>,
+    /// Non-fatal remarks, such as content not matching the extension
+    pub warnings: Vec<String>,
+    /// Files that could not be classified or planned
+    pub failures: Vec<KondoError>,
+}
+
+/// One file's worth of planning, as handed over by [`Planner::plan_each`]
+#[derive(Debug)]
+pub enum PlanItem {
+    Move(PlannedMove),
+    /// A non-fatal remark, such as content not matching the extension
+    Warning(String),
+    /// A file that could not be classified or planned
+    Failure(KondoError),
+}
+
+/// A file whose category was chosen beforehand, e.g. in `kondo tui`
+#[derive(Clone, Debug)]
+pub struct Chosen<'a> {
+    pub source: PathBuf,
+    pub category: &'a Category,
+    /// Name to give the file instead of the one from the category's `rename`
+    pub file_name: Option<OsString>,
+}
+
+// What can be worked out about a file on its own, before it is placed among
+// the other files of the run
+struct Prepared<'a> {
+    source: PathBuf,
+    category: &'a Category,
+    file_name: OsString,
+    target: PathBuf,
+    mode: TransferMode,
+    size: u64,
+    modified: SystemTime,
+    // Whether the target already is what the transfer would make of it
+    transferred: bool,
+    // Whether the target is the source itself, e.g. when the destination
+    // holds the files it organizes
+    in_place: bool,
+}
+
+// A destination taken by a file planned earlier in the run, which may be
+// there already, with what the conflict policies compare
+struct Claim {
+    size: u64,
+    modified: SystemTime,
+    // The claim of the file this one overwrites, when that one is of the run too
+    replaced: Option<Box<Claim>>,
+}
+
+/// Turns classified files into planned moves, resolving name conflicts
+/// against the disk and against the moves planned before
+pub struct Planner {
+    options: Options,
+    claimed: HashMap<PathBuf, Claim>,
+    duplicates: DuplicateIndex,
+}
+
+impl Planner {
+    pub fn new(options: &Options) -> Planner {
+        Planner {
+            options: options.clone(),
+            claimed: HashMap::new(),
+            duplicates: DuplicateIndex::default(),
+        }
+    }
+
+    /// Plan the files that follow with other options, e.g. those of another
+    /// profile, keeping the destinations claimed so far
+    pub fn set_options(&mut self, options: &Options) {
+        self.options = options.clone();
+    }
+
+    /// Classify and plan every file, in order
+    pub fn plan(&mut self, classifier: &Classifier, paths: Vec<PathBuf>) -> Plan {
+        let mut plan = Plan::default();
+
+        let result = self.plan_each(classifier, paths.into_iter(), |item| {
+            match item {
+                PlanItem::Move(planned) => plan.moves.push(planned),
+                PlanItem::Warning(warning) => plan.warnings.push(warning),
+                PlanItem::Failure(e) => plan.failures.push(e),
+            }
+            Ok::<_, std::convert::Infallible>(())
+        });
+        let Ok(()) = result;
+
+        plan
+    }
+
+    /// Classify and plan files as `paths` yields them, handing each planned
+    /// move, warning and failure to `report` in the order of `paths`. Files
+    /// are classified and their dates and tags read on `jobs` threads, but
+    /// placed one at a time, so that conflicts are resolved the same way
+    /// whatever the number of threads. Stops at the first error from `report`.
+    ///
+    /// Earlier moves may be carried out while later files are planned: only
+    /// destinations claimed by this run change on disk, and those are always
+    /// judged by the claims rather than by what is there.
+    pub fn plan_each<E>(
+        &mut self,
+        classifier: &Classifier,
+        paths: impl Iterator<Item = PathBuf> + Send,
+        mut report: impl FnMut(PlanItem) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let options = self.options.clone();
+
+        parallel_map(
+            paths,
+            options.jobs,
+            |path| {
+                let classified = classifier.classify(&path).map(|classification| {
+                    let prepared = classification.category.map(|category| prepare(&path, category, None, &options));
+                    (classification.warning, prepared)
+                });
+                (path, classified)
+            },
+            |(path, classified)| {
+                let (warning, prepared) = match classified {
+                    Ok(classified) => classified,
+                    Err(e) => return report(PlanItem::Failure(KondoError::io(&path, e))),
+                };
+
+                if let Some(warning) = warning {
+                    report(PlanItem::Warning(warning))?;
+                }
+
+                match prepared.map(|prepared| prepared.and_then(|prepared| self.place(prepared))) {
+                    Some(Ok(planned)) => report(PlanItem::Move(planned)),
+                    Some(Err(e)) => report(PlanItem::Failure(KondoError::io(&path, e))),
+                    None => Ok(()),
+                }
+            },
+        )
+    }
+
+    /// Plan files whose category is already chosen, in order, with the
+    /// result for each. Like with `plan_each`, dates and tags are read on
+    /// several threads but the files are placed one at a time.
+    pub fn plan_chosen(&mut self, files: Vec<Chosen>) -> Vec<Result<PlannedMove, KondoError>> {
+        let options = self.options.clone();
+        let mut planned = Vec::new();
+
+        let result = parallel_map(
+            files.into_iter(),
+            options.jobs,
+            |file| {
+                let prepared = prepare(&file.source, file.category, file.file_name.as_deref(), &options);
+                (file.source, prepared)
+            },
+            |(source, prepared)| {
+                let result = prepared.and_then(|prepared| self.place(prepared));
+                planned.push(result.map_err(|e| KondoError::io(&source, e)));
+                Ok::<_, std::convert::Infallible>(())
+            },
+        );
+        let Ok(()) = result;
+
+        planned
+    }
+
+    /// Decide where `source` goes when moved into the category's destination,
+    /// with its placeholders expanded for this file. Duplicates are handled by
+    /// the `on_duplicate` option, and other files whose name is already taken
+    /// on disk or earlier in this run by the conflict policy.
+    pub fn plan_move(&mut self, source: PathBuf, category: &Category) -> std::io::Result<PlannedMove> {
+        let prepared = prepare(&source, category, None, &self.options)?;
+        self.place(prepared)
+    }
+
+    /// Take back a move planned last, e.g. because the user skipped it, so
+    /// that its destination is free again for the files planned after it
+    pub fn forget(&mut self, planned: &PlannedMove) {
+        if matches!(planned.action, Action::Move | Action::Overwrite) {
+            let replaced = self.claimed.remove(&planned.destination).and_then(|claim| claim.replaced);
+            if let Some(replaced) = replaced {
+                self.claimed.insert(planned.destination.clone(), *replaced);
+            }
+            self.duplicates.remove(&planned.source);
+        }
+    }
+
+    // Place a prepared file among the files planned before it
+    fn place(&mut self, prepared: Prepared) -> io::Result<PlannedMove> {
+        let Prepared { source, category, file_name, target, mode, size, modified, transferred, in_place } = prepared;
+
+        // A target claimed earlier in this run may already hold the file
+        // moved there, which is no reason to skip this one
+        let unclaimed = !self.claimed.contains_key(&target);
+        let mut duplicate_of = None;
+        let (destination, action) = if transferred && unclaimed {
+            let reason = format!("already {}", mode.past_tense());
+            (target, Action::Skip(reason))
+        } else if in_place && unclaimed {
+            (target, Action::Skip("already in place".to_string()))
+        } else if let Some(original) = self.find_duplicate(&source, size, &target)? {
+            let planned = self.plan_duplicate(&original, &file_name, &category.extensions, mode);
+            duplicate_of = Some(original);
+            planned
+        } else {
+            self.resolve_conflict(size, modified, &category.extensions, target)?
+        };
+
+        if matches!(action, Action::Move | Action::Overwrite) {
+            let replaced = self.claimed.remove(&destination).map(Box::new);
+            self.claimed.insert(destination.clone(), Claim { size, modified, replaced });
+            if self.options.on_duplicate.is_some() && duplicate_of.is_none() {
+                self.duplicates.add(&source, &destination, size);
+            }
+        }
+
+        Ok(PlannedMove {
+            source,
+            destination,
+            category: category.name.clone(),
+            action,
+            mode,
+            duplicate_of,
+            size,
+        })
+    }
+
+    // A file with the same content as `source`, in the directory it would go
+    // to or among the files planned so far, when duplicates are looked for
+    fn find_duplicate(&mut self, source: &Path, size: u64, target: &Path) -> std::io::Result<Option<PathBuf>> {
+        if self.options.on_duplicate.is_none() {
+            return Ok(None);
+        }
+
+        if let Some(dir) = target.parent() {
+            self.duplicates.add_dir(dir, |path| self.claimed.contains_key(path))?;
+        }
+        self.duplicates.find(source, size)
+    }
+
+    fn plan_duplicate(
+        &self,
+        original: &Path,
+        file_name: &OsStr,
+        extensions: &[String],
+        mode: TransferMode,
+    ) -> (PathBuf, Action) {
+        // Copies and links are made to leave the source untouched
+        if mode != TransferMode::Move {
+            return (original.to_path_buf(), Action::Skip("duplicate".to_string()));
+        }
+
+        match self.options.on_duplicate {
+            Some(DuplicateAction::Delete) => (original.to_path_buf(), Action::Delete),
+            Some(DuplicateAction::Hardlink) => (original.to_path_buf(), Action::Hardlink),
+            Some(DuplicateAction::Move) => {
+                let target = self.options.duplicates.join(file_name);
+                if target.exists() || self.claimed.contains_key(&target) {
+                    (free_path(&target, None, extensions, &self.claimed), Action::Move)
+                } else {
+                    (target, Action::Move)
+                }
+            }
+            Some(DuplicateAction::Skip) | None => (original.to_path_buf(), Action::Skip("duplicate".to_string())),
+        }
+    }
+
+    // Where the file goes and how, applying the conflict policy when `target`
+    // is taken on disk or earlier in this run. A file of the run is compared
+    // as it was when planned, as it may have been moved there already.
+    fn resolve_conflict(
+        &self,
+        size: u64,
+        modified: SystemTime,
+        extensions: &[String],
+        target: PathBuf,
+    ) -> std::io::Result<(PathBuf, Action)> {
+        let claimed = &self.claimed;
+
+        let (taken, existing_size, existing_modified) = match claimed.get(&target) {
+            Some(claim) => ("another file in this run goes there", claim.size, claim.modified),
+            None if target.exists() => {
+                let metadata = fs::metadata(&target)?;
+                ("destination already exists", metadata.len(), metadata.modified()?)
+            }
+            None => return Ok((target, Action::Move)),
+        };
+
+        Ok(match self.options.on_conflict {
+            ConflictPolicy::Skip => (target, Action::Skip(taken.to_string())),
+            ConflictPolicy::Overwrite => (target, Action::Overwrite),
+            ConflictPolicy::Rename => (free_path(&target, None, extensions, claimed), Action::Move),
+            ConflictPolicy::Timestamp => {
+                let stamp = DateTime::<Local>::from(modified).format("%Y%m%d-%H%M%S").to_string();
+                (free_path(&target, Some(stamp), extensions, claimed), Action::Move)
+            }
+            ConflictPolicy::Newer if modified > existing_modified => (target, Action::Overwrite),
+            ConflictPolicy::Newer => (target, Action::Skip("destination is at least as new".to_string())),
+            ConflictPolicy::Larger if size > existing_size => (target, Action::Overwrite),
+            ConflictPolicy::Larger => (target, Action::Skip("destination is at least as large".to_string())),
+        })
+    }
+}
+
+// Work out the target of `source` and whether it is already there, which
+// depends on nothing but the file itself and what was on disk before the run.
+// A `file_name` given replaces the one the category would give.
+fn prepare<'a>(
+    source: &Path,
+    category: &'a Category,
+    file_name: Option<&OsStr>,
+    options: &Options,
+) -> io::Result<Prepared<'a>> {
+    let original_name = source.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("Not a file: {}", source.display()))
+    })?;
+    let values = TemplateValues::for_file(source, category, options)?;
+    let file_name = match (file_name, &category.rename) {
+        (Some(file_name), _) => file_name.to_os_string(),
+        (None, Some(rename)) => expand_name(rename, &values, source),
+        (None, None) => original_name.to_os_string(),
+    };
+    let target = expand_template(&category.destination, &values, &options.undated).join(file_name);
+    let mode = category.mode.unwrap_or(options.mode);
+    let metadata = fs::metadata(source)?;
+
+    Ok(Prepared {
+        source: source.to_path_buf(),
+        category,
+        file_name: original_name.to_os_string(),
+        size: metadata.len(),
+        modified: metadata.modified()?,
+        transferred: is_transferred(source, &target, mode),
+        in_place: source == target || is_same_file(source, &target).unwrap_or(false),
+        target,
+        mode,
+    })
+}
+
+// `photo.jpg` with suffix `1` becomes `photo (1).jpg`, and `b.tar.gz`
+// becomes `b (1).tar.gz` when `tar.gz` is one of `extensions`
+fn suffixed_path(path: &Path, suffix: &str, extensions: &[String]) -> PathBuf {
+    // How many dotted parts at the end of the name make up the extension
+    let parts = match matching_extension(path, extensions) {
+        Some(extension) => extension.matches('.').count() + 1,
+        None => usize::from(path.extension().is_some()),
+    };
+
+    // Split on the name itself rather than a lossy copy, which could cut a
+    // name that is not UTF-8 in the middle of a character
+    let mut stem = PathBuf::from(path.file_name().unwrap_or_default());
+    let mut extension = OsString::new();
+    for _ in 0..parts {
+        let (Some(part), Some(rest)) = (stem.extension(), stem.file_stem()) else {
+            break;
+        };
+        let mut dotted = OsString::from(".");
+        dotted.push(part);
+        dotted.push(&extension);
+        extension = dotted;
+        stem = PathBuf::from(rest);
+    }
+
+    let mut file_name = stem.into_os_string();
+    file_name.push(format!(" ({})", suffix));
+    file_name.push(extension);
+    path.with_file_name(file_name)
+}
+
+// First free variant of `path`, counting up from `first`
+fn free_path(path: &Path, first: Option<String>, extensions: &[String], claimed: &HashMap<PathBuf, Claim>) -> PathBuf {
+    let is_free = |p: &Path| !p.exists() && !claimed.contains_key(p);
+
+    if let Some(suffix) = first {
+        let candidate = suffixed_path(path, &suffix, extensions);
+        if is_free(&candidate) {
+            return candidate;
+        }
+    }
+
+    (1..)
+        .map(|n| suffixed_path(path, &n.to_string(), extensions))
+        .find(|candidate| is_free(candidate))
+        .expect("ran out of numeric suffixes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::fs::File;
+    use std::time::Duration;
+
+    fn extensions(list: &[&str]) -> Vec<String> {
+        list.iter().map(|ext| ext.to_string()).collect()
+    }
+
+    // A config sending text files to `dest` with the given conflict policy
+    fn config(dir: &Path, policy: &str) -> Config {
+        let text = format!(
+            "[options]\non_conflict = \"{}\"\n\n\
+             [categories.documents]\nextensions = [\"txt\"]\ndestination = \"dest\"\n",
+            policy
+        );
+        Config::parse(&text, dir).unwrap()
+    }
+
+    // Write `content` to `dir/name`, modified `age` seconds ago
+    fn file(dir: &Path, name: &str, content: &str, age: u64) -> PathBuf {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(age);
+        File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        path
+    }
+
+    // The planned destination of each file, relative to `dir`, and its action
+    fn plan(dir: &Path, policy: &str, files: &[PathBuf]) -> Vec<(PathBuf, Action)> {
+        let config = config(dir, policy);
+        let mut planner = Planner::new(&config.options);
+        files
+            .iter()
+            .map(|source| {
+                let planned = planner.plan_move(source.clone(), &config.categories[0]).unwrap();
+                (planned.destination.strip_prefix(dir).unwrap().to_path_buf(), planned.action)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn suffixes_go_before_the_longest_extension() {
+        let archives = extensions(&["gz", "tar.gz"]);
+        assert_eq!(suffixed_path(Path::new("/d/b.tar.gz"), "1", &archives), Path::new("/d/b (1).tar.gz"));
+        assert_eq!(suffixed_path(Path::new("/d/b.TAR.GZ"), "2", &archives), Path::new("/d/b (2).TAR.GZ"));
+        assert_eq!(suffixed_path(Path::new("/d/b.tar.gz"), "1", &[]), Path::new("/d/b.tar (1).gz"));
+        assert_eq!(suffixed_path(Path::new("/d/photo.jpg"), "1", &[]), Path::new("/d/photo (1).jpg"));
+        assert_eq!(suffixed_path(Path::new("/d/README"), "1", &[]), Path::new("/d/README (1)"));
+        assert_eq!(suffixed_path(Path::new("/d/.bashrc"), "1", &[]), Path::new("/d/.bashrc (1)"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn names_that_are_not_utf8_keep_their_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"/d/caf\xe9.\xff"));
+        let suffixed = suffixed_path(path, "1", &extensions(&["txt"]));
+        assert_eq!(suffixed.as_os_str().as_bytes(), b"/d/caf\xe9 (1).\xff");
+
+        let path = Path::new(OsStr::from_bytes(b"/d/\xff.tar.gz"));
+        let suffixed = suffixed_path(path, "2", &extensions(&["tar.gz"]));
+        assert_eq!(suffixed.as_os_str().as_bytes(), b"/d/\xff (2).tar.gz");
+    }
+
+    #[test]
+    fn files_already_at_their_target_are_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        // The file by its own path, and by another one
+        let sources = [file(dir, "dest/a.txt", "a", 0), dir.join("dest/../dest/a.txt")];
+
+        for policy in ConflictPolicy::NAMES {
+            let skipped = (PathBuf::from("dest/a.txt"), Action::Skip("already in place".to_string()));
+            assert_eq!(plan(dir, policy, &sources), [skipped.clone(), skipped], "{}", policy);
+        }
+    }
+
+    #[test]
+    fn conflicts_with_the_disk_follow_the_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        file(dir, "dest/a.txt", "existing", 100);
+        let newer_and_smaller = file(dir, "src/a.txt", "new", 10);
+        let source = [newer_and_smaller];
+
+        let skip = Action::Skip("destination already exists".to_string());
+        assert_eq!(plan(dir, "skip", &source), [("dest/a.txt".into(), skip)]);
+        assert_eq!(plan(dir, "overwrite", &source), [("dest/a.txt".into(), Action::Overwrite)]);
+        assert_eq!(plan(dir, "rename", &source), [("dest/a (1).txt".into(), Action::Move)]);
+        assert_eq!(plan(dir, "newer", &source), [("dest/a.txt".into(), Action::Overwrite)]);
+        let smaller = Action::Skip("destination is at least as large".to_string());
+        assert_eq!(plan(dir, "larger", &source), [("dest/a.txt".into(), smaller)]);
+
+        let (destination, action) = plan(dir, "timestamp", &source).remove(0);
+        let name = destination.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(name.starts_with("a (20") && name.ends_with(").txt"), "{}", name);
+        assert_eq!(action, Action::Move);
+    }
+
+    #[test]
+    fn conflicts_within_the_run_follow_the_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let files = [file(dir, "src/1/a.txt", "older", 100), file(dir, "src/2/a.txt", "new", 10)];
+
+        let (first, taken) = (("dest/a.txt".into(), Action::Move), "another file in this run goes there");
+        assert_eq!(plan(dir, "skip", &files), [first.clone(), ("dest/a.txt".into(), Action::Skip(taken.into()))]);
+        assert_eq!(plan(dir, "overwrite", &files), [first.clone(), ("dest/a.txt".into(), Action::Overwrite)]);
+        assert_eq!(plan(dir, "rename", &files), [first.clone(), ("dest/a (1).txt".into(), Action::Move)]);
+        assert_eq!(plan(dir, "newer", &files), [first.clone(), ("dest/a.txt".into(), Action::Overwrite)]);
+        let smaller = Action::Skip("destination is at least as large".to_string());
+        assert_eq!(plan(dir, "larger", &files), [first.clone(), ("dest/a.txt".into(), smaller)]);
+
+        let planned = plan(dir, "timestamp", &files);
+        assert_eq!(planned[0], first);
+        assert!(planned[1].0.to_string_lossy().starts_with("dest/a (20"), "{:?}", planned[1]);
+    }
+
+    #[test]
+    fn a_forgotten_overwrite_gives_the_destination_back_to_the_earlier_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let config = config(dir, "larger");
+        let documents = &config.categories[0];
+        let mut planner = Planner::new(&config.options);
+
+        planner.plan_move(file(dir, "src/1/a.txt", "small", 0), documents).unwrap();
+        let larger = planner.plan_move(file(dir, "src/2/a.txt", "much larger", 0), documents).unwrap();
+        assert_eq!(larger.action, Action::Overwrite);
+        planner.forget(&larger);
+
+        // Compared with the first file again, not with the forgotten one
+        let medium = planner.plan_move(file(dir, "src/3/a.txt", "medium", 0), documents).unwrap();
+        assert_eq!(medium.action, Action::Overwrite);
+    }
+}
@@ -0,0 +1,358 @@
+use crate::error::KondoError;
+use crate::execute::Outcome;
+use crate::plan::{Action, PlannedMove};
+use crate::transfer::TransferMode;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// How a run is reported on standard output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One line per file, for people
+    #[default]
+    Text,
+    /// A single JSON document once the run is over
+    Json,
+    /// One JSON object per line as files are handled, then the summary
+    Ndjson,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&'static str; 3] = ["text", "json", "ndjson"];
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _ => Err(format!("unknown output format '{}'", s)),
+        }
+    }
+}
+
+/// What became of a file, in a [`FileRecord`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordAction {
+    /// Moved, copied or linked to its destination, as the mode says
+    Moved,
+    Skipped,
+    Failed,
+    /// Deleted, linked or moved away as a duplicate of the destination
+    Duplicate,
+}
+
+/// One file of a run, for `--output json` and `ndjson`. Paths that are not
+/// valid UTF-8 have the offending bytes replaced.
+#[derive(Clone, Debug, Serialize)]
+pub struct FileRecord {
+    pub source: String,
+    /// Where the file went, or for a duplicate the identical file. Missing
+    /// when the file failed before it was planned.
+    pub destination: Option<String>,
+    pub category: Option<String>,
+    pub action: RecordAction,
+    pub mode: Option<TransferMode>,
+    /// Why the file was skipped or failed, or what was done with a duplicate
+    pub reason: Option<String>,
+    pub bytes: Option<u64>,
+}
+
+impl FileRecord {
+    /// The record of a planned move once carried out
+    pub fn executed(planned: &PlannedMove, outcome: &Outcome) -> FileRecord {
+        let (action, reason) = match outcome {
+            Outcome::Transferred if planned.duplicate_of.is_some() => {
+                (RecordAction::Duplicate, Some("moved to the duplicates folder".to_string()))
+            }
+            Outcome::Transferred => (RecordAction::Moved, None),
+            Outcome::Deleted => (RecordAction::Duplicate, Some("deleted".to_string())),
+            Outcome::Linked => (RecordAction::Duplicate, Some("replaced with a hard link".to_string())),
+            Outcome::Skipped(reason) => (RecordAction::Skipped, Some(reason.clone())),
+            Outcome::Failed(e) => (RecordAction::Failed, Some(failure_reason(e))),
+        };
+        FileRecord::new(planned, action, reason)
+    }
+
+    /// The record of a planned move in a dry run, as if it had gone through
+    pub fn planned(planned: &PlannedMove) -> FileRecord {
+        let (action, reason) = match &planned.action {
+            Action::Move if planned.duplicate_of.is_some() => {
+                (RecordAction::Duplicate, Some("moved to the duplicates folder".to_string()))
+            }
+            Action::Move | Action::Overwrite => (RecordAction::Moved, None),
+            Action::Skip(reason) => (RecordAction::Skipped, Some(reason.clone())),
+            Action::Delete => (RecordAction::Duplicate, Some("deleted".to_string())),
+            Action::Hardlink => (RecordAction::Duplicate, Some("replaced with a hard link".to_string())),
+        };
+        FileRecord::new(planned, action, reason)
+    }
+
+    /// The record of a file, or directory, that failed before it was planned
+    pub fn failed(error: &KondoError) -> FileRecord {
+        let source = match error {
+            KondoError::Io { path, .. } | KondoError::PermissionDenied { path } => lossy(path),
+            _ => String::new(),
+        };
+
+        FileRecord {
+            source,
+            destination: None,
+            category: None,
+            action: RecordAction::Failed,
+            mode: None,
+            reason: Some(failure_reason(error)),
+            bytes: None,
+        }
+    }
+
+    fn new(planned: &PlannedMove, action: RecordAction, reason: Option<String>) -> FileRecord {
+        FileRecord {
+            source: lossy(&planned.source),
+            destination: Some(lossy(&planned.destination)),
+            category: Some(planned.category.clone()),
+            action,
+            mode: Some(planned.mode),
+            reason,
+            bytes: Some(planned.size),
+        }
+    }
+}
+
+fn lossy(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+// The error without the path, which the record already has
+fn failure_reason(error: &KondoError) -> String {
+    match error {
+        KondoError::Io { source, .. } => source.to_string(),
+        KondoError::PermissionDenied { .. } => "permission denied".to_string(),
+        _ => error.to_string(),
+    }
+}
+
+/// Counts over all the records of a run
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Summary {
+    pub files: usize,
+    pub moved: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub duplicate: usize,
+    /// Total size of the files moved, copied or linked
+    pub bytes: u64,
+    /// Whether nothing was actually changed
+    pub dry_run: bool,
+}
+
+impl Summary {
+    fn add(&mut self, record: &FileRecord) {
+        self.files += 1;
+        match record.action {
+            RecordAction::Moved => {
+                self.moved += 1;
+                self.bytes += record.bytes.unwrap_or(0);
+            }
+            RecordAction::Skipped => self.skipped += 1,
+            RecordAction::Failed => self.failed += 1,
+            RecordAction::Duplicate => self.duplicate += 1,
+        }
+    }
+}
+
+// A line of NDJSON output
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Line<'a> {
+    File(&'a FileRecord),
+    Summary(&'a Summary),
+}
+
+// The whole of JSON output
+#[derive(Serialize)]
+struct Document<'a> {
+    files: &'a [FileRecord],
+    summary: &'a Summary,
+}
+
+/// Writes the records of a run to `output` as JSON or NDJSON. NDJSON lines
+/// are written and flushed as soon as they are recorded, so that they can be
+/// followed while the run goes on.
+pub struct Reporter<W: Write> {
+    output: W,
+    format: OutputFormat,
+    // Only kept for JSON, which is written in one go
+    records: Vec<FileRecord>,
+    summary: Summary,
+}
+
+impl<W: Write> Reporter<W> {
+    pub fn new(output: W, format: OutputFormat, dry_run: bool) -> Reporter<W> {
+        Reporter {
+            output,
+            format,
+            records: Vec::new(),
+            summary: Summary { dry_run, ..Summary::default() },
+        }
+    }
+
+    pub fn record(&mut self, record: FileRecord) -> io::Result<()> {
+        self.summary.add(&record);
+        match self.format {
+            OutputFormat::Ndjson => write_line(&mut self.output, &Line::File(&record)),
+            _ => {
+                self.records.push(record);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn summary(&self) -> &Summary {
+        &self.summary
+    }
+
+    /// Write the summary, and with JSON every record before it
+    pub fn finish(&mut self) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Ndjson => write_line(&mut self.output, &Line::Summary(&self.summary)),
+            _ => {
+                let document = Document { files: &self.records, summary: &self.summary };
+                serde_json::to_writer_pretty(&mut self.output, &document)?;
+                writeln!(self.output)?;
+                self.output.flush()
+            }
+        }
+    }
+}
+
+fn write_line(output: &mut impl Write, line: &Line) -> io::Result<()> {
+    serde_json::to_writer(&mut *output, line)?;
+    writeln!(output)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::path::PathBuf;
+
+    fn planned(name: &str, action: Action, size: u64) -> PlannedMove {
+        PlannedMove {
+            source: PathBuf::from("/in").join(name),
+            destination: PathBuf::from("/out").join(name),
+            category: "documents".to_string(),
+            action,
+            mode: TransferMode::Move,
+            duplicate_of: None,
+            size,
+        }
+    }
+
+    fn records() -> Vec<FileRecord> {
+        let mut duplicate = planned("c.txt", Action::Delete, 3);
+        duplicate.duplicate_of = Some(PathBuf::from("/out/a.txt"));
+        vec![
+            FileRecord::executed(&planned("a.txt", Action::Move, 10), &Outcome::Transferred),
+            FileRecord::executed(&planned("b.txt", Action::Move, 20), &Outcome::Skipped("already moved".to_string())),
+            FileRecord::executed(&duplicate, &Outcome::Deleted),
+            FileRecord::planned(&planned("d.txt", Action::Overwrite, 5)),
+            FileRecord::failed(&KondoError::PermissionDenied { path: PathBuf::from("/in/e.txt") }),
+        ]
+    }
+
+    fn report(format: OutputFormat) -> (String, Summary) {
+        let mut reporter = Reporter::new(Vec::new(), format, false);
+        for record in records() {
+            reporter.record(record).unwrap();
+        }
+        reporter.finish().unwrap();
+        let summary = reporter.summary().clone();
+        (String::from_utf8(reporter.output).unwrap(), summary)
+    }
+
+    #[test]
+    fn records_have_every_field() {
+        let records = serde_json::to_value(records()).unwrap();
+        assert_eq!(
+            records[0],
+            json!({
+                "source": "/in/a.txt",
+                "destination": "/out/a.txt",
+                "category": "documents",
+                "action": "moved",
+                "mode": "move",
+                "reason": null,
+                "bytes": 10,
+            })
+        );
+        assert_eq!(records[1]["action"], "skipped");
+        assert_eq!(records[1]["reason"], "already moved");
+        assert_eq!(records[2]["action"], "duplicate");
+        assert_eq!(records[2]["reason"], "deleted");
+        assert_eq!(records[3]["action"], "moved");
+        assert_eq!(
+            records[4],
+            json!({
+                "source": "/in/e.txt",
+                "destination": null,
+                "category": null,
+                "action": "failed",
+                "mode": null,
+                "reason": "permission denied",
+                "bytes": null,
+            })
+        );
+    }
+
+    #[test]
+    fn the_summary_counts_each_action() {
+        let (_, summary) = report(OutputFormat::Json);
+        let summary = serde_json::to_value(summary).unwrap();
+        assert_eq!(
+            summary,
+            json!({
+                "files": 5,
+                "moved": 2,
+                "skipped": 1,
+                "failed": 1,
+                "duplicate": 1,
+                "bytes": 15,
+                "dry_run": false,
+            })
+        );
+    }
+
+    #[test]
+    fn json_is_one_document_with_the_files_and_the_summary() {
+        let (output, summary) = report(OutputFormat::Json);
+        let document: Value = serde_json::from_str(&output).unwrap();
+
+        assert_eq!(document.as_object().unwrap().len(), 2);
+        assert_eq!(document["files"], serde_json::to_value(records()).unwrap());
+        assert_eq!(document["summary"], serde_json::to_value(summary).unwrap());
+    }
+
+    #[test]
+    fn ndjson_has_a_line_per_file_then_the_summary() {
+        let (output, summary) = report(OutputFormat::Ndjson);
+        let lines: Vec<Value> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+
+        assert_eq!(lines.len(), 6);
+        for (line, record) in lines.iter().zip(serde_json::to_value(records()).unwrap().as_array().unwrap()) {
+            let mut expected = record.clone();
+            expected["type"] = json!("file");
+            assert_eq!(line, &expected);
+        }
+        let mut expected = serde_json::to_value(summary).unwrap();
+        expected["type"] = json!("summary");
+        assert_eq!(lines[5], expected);
+    }
+}
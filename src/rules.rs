@@ -0,0 +1,275 @@
+use crate::classify::matching_extension;
+use crate::config::Category;
+use crate::sniff::sniff_file_type;
+use crate::template::{file_time, DateSource};
+use globset::GlobMatcher;
+use regex::Regex;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// A rule from `[[rules]]`. Files meeting all of its conditions go to its
+/// destination, as if they formed a category of their own.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub conditions: Conditions,
+    /// Where matching files go. The extensions are those of the conditions.
+    pub category: Category,
+}
+
+/// The conditions of a rule, all of which a file must meet. A rule without
+/// any conditions matches every file.
+#[derive(Clone, Debug, Default)]
+pub struct Conditions {
+    /// Matched against the file name, ignoring case
+    pub glob: Option<GlobMatcher>,
+    /// Matched against the file name
+    pub regex: Option<Regex>,
+    /// Lowercase extensions without the leading dot, any of which will do
+    pub extensions: Vec<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub min_age: Option<Duration>,
+    pub max_age: Option<Duration>,
+    /// Which time of the file its age is counted from
+    pub age_from: DateSource,
+    /// Matched against the name or the full path of the directory holding the file
+    pub parent: Option<GlobMatcher>,
+    /// Matched against the MIME type, e.g. `image/*`
+    pub mime: Option<GlobMatcher>,
+}
+
+impl Conditions {
+    /// Whether the file meets every condition. The file is only read when a
+    /// condition needs its size, times or content.
+    pub fn matches(&self, path: &Path) -> io::Result<bool> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+        if self.glob.as_ref().is_some_and(|glob| !glob.is_match(name.as_ref())) {
+            return Ok(false);
+        }
+        if self.regex.as_ref().is_some_and(|regex| !regex.is_match(&name)) {
+            return Ok(false);
+        }
+        if !self.extensions.is_empty() && matching_extension(path, &self.extensions).is_none() {
+            return Ok(false);
+        }
+
+        if let Some(parent) = &self.parent {
+            let dir = path.parent().unwrap_or(Path::new(""));
+            if !parent.is_match(dir.file_name().unwrap_or_default()) && !parent.is_match(dir) {
+                return Ok(false);
+            }
+        }
+
+        if self.min_size.is_some() || self.max_size.is_some() {
+            let size = fs::metadata(path)?.len();
+            if self.min_size.is_some_and(|min| size < min) || self.max_size.is_some_and(|max| size > max) {
+                return Ok(false);
+            }
+        }
+
+        if self.min_age.is_some() || self.max_age.is_some() {
+            let time = file_time(&fs::metadata(path)?, self.age_from)?;
+            // Files from the future count as brand new
+            let age = SystemTime::now().duration_since(time).unwrap_or_default();
+            if self.min_age.is_some_and(|min| age < min) || self.max_age.is_some_and(|max| age > max) {
+                return Ok(false);
+            }
+        }
+
+        if let Some(mime) = &self.mime {
+            match mime_type(path)? {
+                Some(mime_type) if mime.is_match(&mime_type) => {}
+                _ => return Ok(false),
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+// The MIME type of a file, from its content where recognised, or else from its extension
+fn mime_type(path: &Path) -> io::Result<Option<String>> {
+    let guess = match sniff_file_type(path)? {
+        Some(format) => mime_guess::from_ext(format),
+        None => mime_guess::from_path(path),
+    };
+    Ok(guess.first().map(|mime| mime.essence_str().to_string()))
+}
+
+/// Parse a size such as `2GB`, `512 KiB` or `100`, in bytes. Units are
+/// powers of 1000 (`KB`, `MB`, `GB`, `TB`) or of 1024 (`KiB`, `MiB`, ...).
+pub(crate) fn parse_size(text: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid size '{}'", text);
+
+    let (number, unit) = split_number(text).ok_or_else(invalid)?;
+    let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => return Err(invalid()),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Parse an age such as `30d` or `12 h`. Units are `s`, `m` (minutes), `h`,
+/// `d`, `w` and `y` (365 days).
+pub(crate) fn parse_age(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid age '{}'", text);
+
+    let (number, unit) = split_number(text).ok_or_else(invalid)?;
+    let seconds: u64 = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "y" => 365 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+
+    Ok(Duration::from_secs_f64(number * seconds as f64))
+}
+
+// `2.5 GB` becomes `(2.5, "GB")`
+fn split_number(text: &str) -> Option<(f64, &str)> {
+    let text = text.trim();
+    let end = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+    let number = text[..end].parse::<f64>().ok()?;
+    Some((number, text[end..].trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classify::Classifier;
+    use crate::config::Config;
+
+    // The conditions of the only rule of the config
+    fn conditions(rule: &str) -> Conditions {
+        let text = format!("[[rules]]\n{}\ndestination = \"/sorted\"\n", rule);
+        Config::parse(&text, Path::new("/base")).unwrap().rules.remove(0).conditions
+    }
+
+    fn file(dir: &Path, name: &str, size: usize, age: Duration) -> std::path::PathBuf {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "x".repeat(size)).unwrap();
+        fs::File::options().write(true).open(&path).unwrap().set_modified(SystemTime::now() - age).unwrap();
+        path
+    }
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[test]
+    fn names_are_matched_by_glob_regex_and_extension() {
+        let glob = conditions("glob = \"invoice*\"");
+        assert!(glob.matches(Path::new("/in/Invoice-2024.pdf")).unwrap());
+        assert!(!glob.matches(Path::new("/in/2024-invoice.pdf")).unwrap());
+
+        let regex = conditions("regex = '^IMG_\\d{4}'");
+        assert!(regex.matches(Path::new("/in/IMG_0001.jpg")).unwrap());
+        assert!(!regex.matches(Path::new("/in/img_0001.jpg")).unwrap());
+
+        let extensions = conditions("extensions = [\"tar.gz\", \"zip\"]");
+        assert!(extensions.matches(Path::new("/in/a.TAR.GZ")).unwrap());
+        assert!(!extensions.matches(Path::new("/in/a.gz")).unwrap());
+    }
+
+    #[test]
+    fn parents_are_matched_by_name_or_full_path() {
+        let by_name = conditions("parent = \"screen*\"");
+        assert!(by_name.matches(Path::new("/home/me/Screenshots/a.png")).unwrap());
+        assert!(!by_name.matches(Path::new("/home/screenshots/me/a.png")).unwrap());
+
+        let by_path = conditions("parent = \"/home/*/Downloads\"");
+        assert!(by_path.matches(Path::new("/home/me/Downloads/a.png")).unwrap());
+        assert!(!by_path.matches(Path::new("/srv/Downloads/a.png")).unwrap());
+    }
+
+    #[test]
+    fn sizes_and_ages_are_inclusive_bounds() {
+        let dir = tempfile::tempdir().unwrap();
+        let sizes = conditions("min_size = \"10\"\nmax_size = \"20\"");
+        assert!(!sizes.matches(&file(dir.path(), "9", 9, Duration::ZERO)).unwrap());
+        assert!(sizes.matches(&file(dir.path(), "10", 10, Duration::ZERO)).unwrap());
+        assert!(sizes.matches(&file(dir.path(), "20", 20, Duration::ZERO)).unwrap());
+        assert!(!sizes.matches(&file(dir.path(), "21", 21, Duration::ZERO)).unwrap());
+
+        let ages = conditions("min_age = \"7d\"\nmax_age = \"30d\"");
+        assert!(!ages.matches(&file(dir.path(), "new", 1, DAY)).unwrap());
+        assert!(ages.matches(&file(dir.path(), "old", 1, 10 * DAY)).unwrap());
+        assert!(!ages.matches(&file(dir.path(), "ancient", 1, 40 * DAY)).unwrap());
+
+        // The file is only looked at when a condition needs it
+        assert!(sizes.matches(&dir.path().join("missing")).is_err());
+        assert!(conditions("glob = \"*.txt\"").matches(&dir.path().join("missing.txt")).unwrap());
+    }
+
+    #[test]
+    fn the_first_matching_rule_wins_before_the_categories_and_the_default() {
+        let text = r#"
+[[rules]]
+name = "large"
+min_size = "100"
+destination = "/large"
+
+[[rules]]
+name = "invoices"
+glob = "invoice*"
+destination = "/invoices"
+
+[categories.documents]
+extensions = ["pdf"]
+destination = "/documents"
+
+[default]
+destination = "/misc"
+"#;
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::parse(text, dir.path()).unwrap();
+        let classifier = Classifier::new(&config);
+        let category_of = |path: &Path| classifier.classify(path).unwrap().category.map(|c| c.name.clone());
+
+        let big_invoice = file(dir.path(), "invoice-big.pdf", 200, Duration::ZERO);
+        let invoice = file(dir.path(), "invoice.pdf", 10, Duration::ZERO);
+        let report = file(dir.path(), "report.pdf", 10, Duration::ZERO);
+        let other = file(dir.path(), "notes.md", 10, Duration::ZERO);
+        assert_eq!(category_of(&big_invoice).as_deref(), Some("large"));
+        assert_eq!(category_of(&invoice).as_deref(), Some("invoices"));
+        assert_eq!(category_of(&report).as_deref(), Some("documents"));
+        assert_eq!(category_of(&other).as_deref(), Some("default"));
+    }
+
+    #[test]
+    fn sizes_take_decimal_and_binary_units() {
+        assert_eq!(parse_size("100"), Ok(100));
+        assert_eq!(parse_size("2GB"), Ok(2_000_000_000));
+        assert_eq!(parse_size("512 KiB"), Ok(512 * 1024));
+        assert_eq!(parse_size("1.5mb"), Ok(1_500_000));
+        assert_eq!(parse_size(" 4 GiB "), Ok(4 << 30));
+        assert!(parse_size("GB").is_err());
+        assert!(parse_size("2 GBs").is_err());
+        assert!(parse_size("-1").is_err());
+    }
+
+    #[test]
+    fn ages_take_units_up_to_years() {
+        assert_eq!(parse_age("30d"), Ok(Duration::from_secs(30 * 24 * 60 * 60)));
+        assert_eq!(parse_age("12 h"), Ok(Duration::from_secs(12 * 60 * 60)));
+        assert_eq!(parse_age("90m"), Ok(Duration::from_secs(90 * 60)));
+        assert_eq!(parse_age("0.5w"), Ok(Duration::from_secs(7 * 12 * 60 * 60)));
+        assert_eq!(parse_age("1y"), Ok(Duration::from_secs(365 * 24 * 60 * 60)));
+        assert!(parse_age("30").is_err());
+        assert!(parse_age("30D").is_err());
+    }
+}
@@ -0,0 +1,371 @@
+use crate::config::Config;
+use crate::error::KondoError;
+use crate::template::template_root;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Name of the file listing what to leave alone in a source directory, in
+/// `.gitignore` syntax
+pub const IGNORE_FILE: &str = ".kondoignore";
+
+/// What [`collect_files`] should look at below the source directory
+pub struct ScanOptions {
+    max_depth: usize,
+    exclude: GlobSet,
+    // Destinations of categories and rules (up to their first placeholder)
+    // and duplicates folder, never descended into. Each is kept absolute and
+    // lexically normalized, and also with its existing part resolved through
+    // symlinks, so that it is recognised whether it exists yet or not.
+    skip_dirs: Vec<PathBuf>,
+    // The `ignore` option of the config
+    ignore_patterns: Vec<String>,
+    // Set up by `read_ignores` for one source directory
+    ignore: Option<Gitignore>,
+}
+
+impl ScanOptions {
+    /// `max_depth` is the number of subdirectory levels to descend into,
+    /// 0 for the top level only
+    pub fn new(config: &Config, max_depth: usize) -> Result<ScanOptions, KondoError> {
+        let mut exclude = GlobSetBuilder::new();
+        for pattern in &config.options.exclude {
+            let glob = Glob::new(pattern).map_err(|e| {
+                KondoError::config("options.exclude", format!("invalid pattern '{}': {}", pattern, e))
+            })?;
+            exclude.add(glob);
+        }
+
+        let roots = config
+            .targets()
+            .map(|category| template_root(&category.destination))
+            .chain(config.options.on_duplicate.map(|_| config.options.duplicates.clone()));
+        let mut skip_dirs = Vec::new();
+        // A destination starting with a placeholder has no fixed part
+        for root in roots.filter(|root| !root.as_os_str().is_empty()) {
+            let normal = normalize(&root);
+            let resolved = resolve(&normal);
+            skip_dirs.push(normal);
+            if !skip_dirs.contains(&resolved) {
+                skip_dirs.push(resolved);
+            }
+        }
+
+        Ok(ScanOptions {
+            max_depth,
+            exclude: exclude
+                .build()
+                .map_err(|e| KondoError::config("options.exclude", e.to_string()))?,
+            skip_dirs,
+            ignore_patterns: config.options.ignore.clone(),
+            ignore: None,
+        })
+    }
+
+    /// Leave alone whatever the `ignore` option of the config or the
+    /// `.kondoignore` file of `source_dir` match, with the semantics of
+    /// `.gitignore`. Lines of the file come last, so they can override the
+    /// config, e.g. by negating a pattern with `!`.
+    pub fn read_ignores(&mut self, source_dir: &Path) -> Result<(), KondoError> {
+        let mut builder = GitignoreBuilder::new(source_dir);
+        for pattern in &self.ignore_patterns {
+            builder
+                .add_line(None, pattern)
+                .map_err(|e| KondoError::config("options.ignore", e.to_string()))?;
+        }
+
+        let ignore_file = source_dir.join(IGNORE_FILE);
+        if ignore_file.is_file() {
+            if let Some(e) = builder.add(&ignore_file) {
+                return Err(KondoError::io(&ignore_file, io::Error::new(io::ErrorKind::InvalidData, e)));
+            }
+        }
+
+        let ignore = builder
+            .build()
+            .map_err(|e| KondoError::io(&ignore_file, io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        self.ignore = Some(ignore);
+        Ok(())
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.ignore.as_ref().is_some_and(|ignore| ignore.matched(path, is_dir).is_ignore())
+    }
+
+    /// Whether a file found outside of [`collect_files`], e.g. by a watcher,
+    /// is one the scan would have skipped
+    pub fn is_excluded(&self, source_dir: &Path, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(source_dir) else {
+            return true;
+        };
+
+        let depth = relative.components().count().saturating_sub(1);
+        if depth > self.max_depth || relative == Path::new(IGNORE_FILE) {
+            return true;
+        }
+
+        let ignored = self.ignore.as_ref().is_some_and(|ignore| {
+            ignore.matched_path_or_any_parents(path, false).is_ignore()
+        });
+        if ignored || self.in_destination(source_dir, path) {
+            return true;
+        }
+
+        // Check the file and every directory leading to it
+        relative.ancestors().filter(|a| !a.as_os_str().is_empty()).any(|ancestor| {
+            let name = ancestor.file_name().unwrap_or_default();
+            self.exclude.is_match(name) || self.exclude.is_match(ancestor)
+        })
+    }
+
+    // Whether `path` is inside a destination below `source_dir`, which need
+    // not exist. A destination holding the source directory itself does not
+    // count, or nothing would ever be organized.
+    fn in_destination(&self, source_dir: &Path, path: &Path) -> bool {
+        let inside = |source_dir: &Path, path: &Path| {
+            self.skip_dirs.iter().any(|dir| {
+                dir.starts_with(source_dir) && dir != source_dir && path.starts_with(dir)
+            })
+        };
+
+        let (source_dir, path) = (normalize(source_dir), normalize(path));
+        inside(&source_dir, &path) || inside(&resolve(&source_dir), &resolve(&path))
+    }
+}
+
+/// List the files to organize, descending into subdirectories as far as the
+/// options allow. Symlinks to directories are never followed, so no loops.
+/// Subdirectories that cannot be read are added to `failures` and skipped.
+pub fn collect_files(
+    source_dir: &Path,
+    options: &ScanOptions,
+    failures: &mut Vec<KondoError>,
+) -> Result<Vec<PathBuf>, KondoError> {
+    let mut files = Vec::new();
+    scan_files(source_dir, options, failures, |path| files.push(path))?;
+    Ok(files)
+}
+
+/// Like [`collect_files`], but hand each file to `on_file` as soon as it is
+/// found, so that the files can be worked on while the scan goes on. Files
+/// come in sorted order all the same.
+pub fn scan_files(
+    source_dir: &Path,
+    options: &ScanOptions,
+    failures: &mut Vec<KondoError>,
+    mut on_file: impl FnMut(PathBuf),
+) -> Result<(), KondoError> {
+    // Not being able to read the source itself is fatal
+    let entries = read_sorted_dir(source_dir, failures).map_err(|e| KondoError::io(source_dir, e))?;
+    scan_entries(source_dir, entries, 0, options, failures, &mut on_file);
+    Ok(())
+}
+
+// Go through the entries of a directory in order, descending into each
+// subdirectory in its place, which keeps all the paths sorted
+fn scan_entries(
+    source_dir: &Path,
+    entries: Vec<fs::DirEntry>,
+    depth: usize,
+    options: &ScanOptions,
+    failures: &mut Vec<KondoError>,
+    on_file: &mut impl FnMut(PathBuf),
+) {
+    for entry in entries {
+        let path = entry.path();
+
+        // Patterns match either the entry's name or its path below the source
+        let relative = path.strip_prefix(source_dir).unwrap_or(&path);
+        if options.exclude.is_match(entry.file_name()) || options.exclude.is_match(relative) {
+            continue;
+        }
+
+        let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+        if options.is_ignored(&path, is_dir) || relative == Path::new(IGNORE_FILE) {
+            continue;
+        }
+
+        if is_dir {
+            // Destinations may be created by moves made while the scan goes on
+            if depth < options.max_depth && !options.in_destination(source_dir, &path) {
+                match read_sorted_dir(&path, failures) {
+                    Ok(entries) => scan_entries(source_dir, entries, depth + 1, options, failures, on_file),
+                    Err(e) => failures.push(KondoError::io(path, e)),
+                }
+            }
+        } else if !path.is_dir() {
+            on_file(path);
+        }
+    }
+}
+
+// `path` made absolute, with `.` and `..` worked out without looking at the disk
+fn normalize(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut normal = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+// `path` with its longest existing part resolved through symlinks
+fn resolve(path: &Path) -> PathBuf {
+    for ancestor in path.ancestors() {
+        if let Ok(canonical) = fs::canonicalize(ancestor) {
+            return canonical.join(path.strip_prefix(ancestor).unwrap_or(path));
+        }
+    }
+    path.to_path_buf()
+}
+
+// The entries of `dir` sorted by name. Entries that cannot be read are added
+// to `failures` and left out.
+fn read_sorted_dir(dir: &Path, failures: &mut Vec<KondoError>) -> io::Result<Vec<fs::DirEntry>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        match entry {
+            Ok(entry) => entries.push(entry),
+            Err(e) => failures.push(KondoError::io(dir, e)),
+        }
+    }
+
+    entries.sort_by_key(|entry| entry.file_name());
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn scan_options(dir: &Path, destination: &str) -> ScanOptions {
+        let text = format!("[categories.documents]\nextensions = [\"txt\"]\ndestination = \"{}\"\n", destination);
+        let config = Config::parse(&text, dir).unwrap();
+        ScanOptions::new(&config, usize::MAX).unwrap()
+    }
+
+    #[test]
+    fn destinations_in_the_source_are_excluded_before_they_exist() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("src");
+        fs::create_dir(&source).unwrap();
+        let options = scan_options(dir.path(), "src/Docs");
+
+        // As a watcher sees the first file moved into a new destination
+        let moved = source.join("Docs").join("a.txt");
+        fs::create_dir(source.join("Docs")).unwrap();
+        fs::write(&moved, "a").unwrap();
+        assert!(options.is_excluded(&source, &moved));
+        assert!(options.is_excluded(&source, &source.join("Docs/sub/b.txt")));
+        assert!(!options.is_excluded(&source, &source.join("Docsx/a.txt")));
+        assert!(!options.is_excluded(&source, &source.join("a.txt")));
+    }
+
+    #[test]
+    fn destinations_created_after_the_options_are_not_scanned() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("src");
+        fs::create_dir_all(source.join("zz")).unwrap();
+        fs::write(source.join("a.txt"), "a").unwrap();
+        let options = scan_options(dir.path(), "src/zz/Docs/{year}");
+
+        // As if the executor had already moved a file there
+        fs::create_dir(source.join("zz/Docs")).unwrap();
+        fs::write(source.join("zz/Docs/b.txt"), "b").unwrap();
+        fs::write(source.join("zz/c.txt"), "c").unwrap();
+
+        let files = collect_files(&source, &options, &mut Vec::new()).unwrap();
+        assert_eq!(files, [source.join("a.txt"), source.join("zz/c.txt")]);
+    }
+
+    #[test]
+    fn a_destination_holding_the_source_is_not_excluded() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("src");
+        fs::create_dir(&source).unwrap();
+
+        let options = scan_options(dir.path(), ".");
+        assert!(!options.is_excluded(&source, &source.join("a.txt")));
+        let options = scan_options(dir.path(), "src/{year}");
+        assert!(!options.is_excluded(&source, &source.join("a.txt")));
+    }
+
+    // A source holding `files`, with the given `.kondoignore`, scanned with
+    // the `ignore` option set to `config_ignore`
+    fn scan_ignoring(files: &[&str], kondoignore: &str, config_ignore: &[&str], read_ignores: bool) -> Vec<String> {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("src");
+        for name in files {
+            let path = source.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+        fs::write(source.join(IGNORE_FILE), kondoignore).unwrap();
+
+        let text = format!(
+            "[categories.documents]\nextensions = [\"txt\"]\ndestination = \"/docs\"\n[options]\nignore = {:?}\n",
+            config_ignore
+        );
+        let config = Config::parse(&text, dir.path()).unwrap();
+        let mut options = ScanOptions::new(&config, usize::MAX).unwrap();
+        if read_ignores {
+            options.read_ignores(&source).unwrap();
+        }
+
+        let files = collect_files(&source, &options, &mut Vec::new()).unwrap();
+        for file in &files {
+            assert!(!options.is_excluded(&source, file), "{}", file.display());
+        }
+        files.iter().map(|file| file.strip_prefix(&source).unwrap().to_string_lossy().into_owned()).collect()
+    }
+
+    const FILES: [&str; 6] = ["a.log", "keep.log", "build/out.txt", "sub/build/x.txt", "sub/todo.txt", "todo.txt"];
+
+    #[test]
+    fn ignore_patterns_follow_gitignore() {
+        // Negation brings a file back
+        assert_eq!(
+            scan_ignoring(&FILES, "*.log\n!keep.log\n", &[], true),
+            ["build/out.txt", "keep.log", "sub/build/x.txt", "sub/todo.txt", "todo.txt"]
+        );
+
+        // An anchored pattern only matches at the top of the source
+        assert_eq!(
+            scan_ignoring(&FILES, "/todo.txt\n", &[], true),
+            ["a.log", "build/out.txt", "keep.log", "sub/build/x.txt", "sub/todo.txt"]
+        );
+
+        // A directory pattern matches directories at any depth, and not files
+        let files = scan_ignoring(&["build/a.txt", "sub/build/b.txt", "sub/c.txt", "x/build"], "build/\n", &[], true);
+        assert_eq!(files, ["sub/c.txt", "x/build"]);
+    }
+
+    #[test]
+    fn the_ignore_file_can_override_the_config() {
+        assert_eq!(
+            scan_ignoring(&FILES, "!keep.log\n", &["*.log"], true),
+            ["build/out.txt", "keep.log", "sub/build/x.txt", "sub/todo.txt", "todo.txt"]
+        );
+    }
+
+    #[test]
+    fn without_reading_the_ignores_only_the_ignore_file_is_left_out() {
+        let files = scan_ignoring(&FILES, "*\n", &["*.txt"], false);
+        assert_eq!(files, ["a.log", "build/out.txt", "keep.log", "sub/build/x.txt", "sub/todo.txt", "todo.txt"]);
+    }
+
+    #[test]
+    fn paths_are_normalized_without_the_disk() {
+        assert_eq!(normalize(Path::new("/a/./b/../c")), Path::new("/a/c"));
+        assert_eq!(normalize(Path::new("/a/b/")), Path::new("/a/b"));
+    }
+}
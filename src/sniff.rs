@@ -0,0 +1,138 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+// Extensions used for the same on-disk format as the one `sniff_header` reports
+const FORMAT_ALIASES: &[(&str, &[&str])] = &[
+    ("jpg", &["jpeg", "jpe", "jfif"]),
+    ("tiff", &["tif", "dng", "nef", "cr2", "arw"]),
+    ("heic", &["heif", "avif"]),
+    ("mp4", &["m4a", "m4v", "m4b", "3gp"]),
+    ("mkv", &["webm", "mka", "mk3d"]),
+    ("ogg", &["oga", "ogv", "opus", "spx"]),
+    ("gz", &["tgz", "tar.gz"]),
+    ("doc", &["xls", "ppt", "msg", "msi"]),
+    ("zip", &["docx", "xlsx", "pptx", "odt", "ods", "odp", "epub", "jar", "apk", "cbz"]),
+];
+
+pub(crate) fn is_same_format(extension: &str, detected: &str) -> bool {
+    extension == detected
+        || FORMAT_ALIASES
+            .iter()
+            .any(|(format, aliases)| *format == detected && aliases.contains(&extension))
+}
+
+// Identify a file format from its first bytes, returning its usual extension
+fn sniff_header(header: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\xFF\xD8\xFF", "jpg"),
+        (b"\x89PNG\r\n\x1A\n", "png"),
+        (b"GIF87a", "gif"),
+        (b"GIF89a", "gif"),
+        (b"II*\0", "tiff"),
+        (b"MM\0*", "tiff"),
+        (b"%PDF-", "pdf"),
+        (b"{\\rtf", "rtf"),
+        (b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1", "doc"),
+        (b"PK\x03\x04", "zip"),
+        (b"PK\x05\x06", "zip"),
+        (b"\x1F\x8B", "gz"),
+        (b"7z\xBC\xAF\x27\x1C", "7z"),
+        (b"Rar!\x1A\x07", "rar"),
+        (b"OggS", "ogg"),
+        (b"fLaC", "flac"),
+        (b"ID3", "mp3"),
+        (b"\x1A\x45\xDF\xA3", "mkv"),
+    ];
+
+    // Container formats, told apart by a tag further in
+    if header.len() >= 12 && header.starts_with(b"RIFF") {
+        return match &header[8..12] {
+            b"WEBP" => Some("webp"),
+            b"WAVE" => Some("wav"),
+            b"AVI " => Some("avi"),
+            _ => None,
+        };
+    }
+    if header.len() >= 12 && &header[4..8] == b"ftyp" {
+        return match &header[8..12] {
+            b"heic" | b"heix" | b"mif1" | b"msf1" => Some("heic"),
+            b"qt  " => Some("mov"),
+            _ => Some("mp4"),
+        };
+    }
+
+    if is_bmp(header) {
+        return Some("bmp");
+    }
+
+    SIGNATURES
+        .iter()
+        .find(|(magic, _)| header.starts_with(magic))
+        .map(|(_, format)| *format)
+}
+
+// "BM" alone starts plenty of text files, so also check that the reserved
+// bytes are zero and that the DIB header has one of the known sizes
+fn is_bmp(header: &[u8]) -> bool {
+    const DIB_SIZES: &[u32] = &[12, 40, 52, 56, 64, 108, 124];
+
+    if header.len() < 18 || !header.starts_with(b"BM") || header[6..10] != [0; 4] {
+        return false;
+    }
+    let dib_size = u32::from_le_bytes([header[14], header[15], header[16], header[17]]);
+    DIB_SIZES.contains(&dib_size)
+}
+
+pub(crate) fn sniff_file_type(file_path: &Path) -> std::io::Result<Option<&'static str>> {
+    let mut header = Vec::with_capacity(18);
+    File::open(file_path)?.take(18).read_to_end(&mut header)?;
+
+    Ok(sniff_header(&header))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bmp_header(reserved: u8, dib_size: u8) -> Vec<u8> {
+        let mut header = b"BM\x36\x00\x0C\x00".to_vec();
+        header.extend([reserved, 0, 0, 0, 0x36, 0, 0, 0, dib_size, 0, 0, 0]);
+        header
+    }
+
+    #[test]
+    fn formats_are_recognised_by_their_signature() {
+        assert_eq!(sniff_header(b"\xFF\xD8\xFF\xE0\x00\x10JFIF"), Some("jpg"));
+        assert_eq!(sniff_header(b"%PDF-1.7\n"), Some("pdf"));
+        assert_eq!(sniff_header(b"ID3\x04\x00"), Some("mp3"));
+        assert_eq!(sniff_header(b"RIFF\x24\x00\x00\x00WAVEfmt "), Some("wav"));
+        assert_eq!(sniff_header(b"\x00\x00\x00\x18ftypheic"), Some("heic"));
+        assert_eq!(sniff_header(b"\x00\x00\x00\x18ftypisom"), Some("mp4"));
+        assert_eq!(sniff_header(b"RIFF\x24\x00\x00\x00XXXX"), None);
+        assert_eq!(sniff_header(b""), None);
+    }
+
+    #[test]
+    fn bitmaps_need_a_valid_header() {
+        assert_eq!(sniff_header(&bmp_header(0, 40)), Some("bmp"));
+        assert_eq!(sniff_header(&bmp_header(0, 124)), Some("bmp"));
+        assert_eq!(sniff_header(&bmp_header(1, 40)), None);
+        assert_eq!(sniff_header(&bmp_header(0, 41)), None);
+        assert_eq!(sniff_header(b"BMW service notes"), None);
+        assert_eq!(sniff_header(b"BM"), None);
+    }
+
+    #[test]
+    fn bare_frame_syncs_are_not_taken_for_mp3() {
+        assert_eq!(sniff_header(b"\xFF\xFB\x90\x00"), None);
+        assert_eq!(sniff_header(b"\xFF\xF3\x48\xC4"), None);
+    }
+
+    #[test]
+    fn aliases_count_as_the_same_format() {
+        assert!(is_same_format("jpeg", "jpg"));
+        assert!(is_same_format("docx", "zip"));
+        assert!(!is_same_format("txt", "bmp"));
+    }
+}
@@ -0,0 +1,54 @@
+use lofty::config::ParseOptions;
+use lofty::prelude::{Accessor, ItemKey, TaggedFileExt};
+use lofty::probe::Probe;
+use std::path::Path;
+
+/// The tags of an audio file used to place it in a music library
+#[derive(Debug, Default)]
+pub(crate) struct Tags {
+    /// The album artist if set, so that compilations stay together, or else the track artist
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub track: Option<u32>,
+}
+
+/// Read the ID3v2, Vorbis comment, FLAC or other tags of an audio file,
+/// recognised by its content. Files that are not audio or cannot be parsed
+/// have no tags.
+pub(crate) fn read_tags(path: &Path) -> Tags {
+    let tagged = Probe::open(path)
+        .ok()
+        .and_then(|probe| probe.guess_file_type().ok())
+        .and_then(|probe| probe.options(ParseOptions::new().read_properties(false)).read().ok());
+    let Some(tag) = tagged.as_ref().and_then(|file| file.primary_tag().or_else(|| file.first_tag())) else {
+        return Tags::default();
+    };
+
+    // Blank tags are as good as none
+    let text = |value: Option<&str>| value.map(str::trim).filter(|v| !v.is_empty()).map(str::to_string);
+
+    Tags {
+        artist: text(tag.get_string(ItemKey::AlbumArtist)).or_else(|| text(tag.artist().as_deref())),
+        album: text(tag.album().as_deref()),
+        title: text(tag.title().as_deref()),
+        track: tag.track(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn files_that_are_not_audio_have_no_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("song.mp3");
+        fs::write(&path, "not really audio").unwrap();
+
+        let tags = read_tags(&path);
+        assert_eq!((tags.artist, tags.album, tags.title, tags.track), (None, None, None, None));
+        assert!(read_tags(&dir.path().join("missing.mp3")).artist.is_none());
+    }
+}
@@ -0,0 +1,432 @@
+use crate::classify::{get_extension, matching_extension};
+use crate::config::{Category, Options};
+use crate::photo::{capture_date, is_photo};
+use crate::tags::{read_tags, Tags};
+use chrono::{DateTime, Datelike, Local, NaiveDateTime};
+use serde::Deserialize;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// Placeholders a destination template can use, e.g. `~/Pictures/{year}/{month:02}`
+const PLACEHOLDERS: [&str; 10] = [
+    "year", "month", "day", "ext", "category", "parent", "artist", "album", "title", "track",
+];
+
+const DATE_PLACEHOLDERS: [&str; 3] = ["year", "month", "day"];
+const TAG_PLACEHOLDERS: [&str; 4] = ["artist", "album", "title", "track"];
+
+// Placeholders holding a number, which take a zero-padded width such as `:02`
+const NUMERIC: [&str; 4] = ["year", "month", "day", "track"];
+
+// Longest value taken from a tag, in bytes, keeping names well below the usual
+// filesystem limit of 255
+const MAX_TAG_LEN: usize = 200;
+
+/// Which timestamp of a file the `{year}`, `{month}` and `{day}` placeholders
+/// use, for files other than photos with an EXIF capture date, and which one
+/// the age conditions of rules look at
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DateSource {
+    /// When the file was last modified
+    #[default]
+    Modified,
+    /// When the file was created, where the filesystem records it,
+    /// or else when it was last modified
+    Created,
+}
+
+/// The values the placeholders of a template expand to for one file
+pub(crate) struct TemplateValues {
+    /// `None` for photos without a capture date, when not falling back to the file's time
+    date: Option<NaiveDateTime>,
+    tags: Tags,
+    ext: String,
+    category: String,
+    parent: String,
+}
+
+impl TemplateValues {
+    pub(crate) fn for_file(path: &Path, category: &Category, options: &Options) -> io::Result<TemplateValues> {
+        // Dating a file or reading its tags means reading its content, so
+        // only do it when the category needs them
+        let uses = |names: &[&str]| {
+            uses_any(&category.destination.to_string_lossy(), names)
+                || category.rename.as_deref().is_some_and(|rename| uses_any(rename, names))
+        };
+
+        let date = if uses(&DATE_PLACEHOLDERS) {
+            file_date(path, options)?
+        } else {
+            None
+        };
+        let tags = if uses(&TAG_PLACEHOLDERS) {
+            read_tags(path)
+        } else {
+            Tags::default()
+        };
+
+        // Prefer the extension the category matched, so `backup.tar.gz` gives `tar.gz`
+        let ext = matching_extension(path, &category.extensions)
+            .map(str::to_string)
+            .or_else(|| get_extension(path))
+            .unwrap_or_else(|| "none".to_string());
+
+        let parent = path
+            .parent()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Ok(TemplateValues {
+            date,
+            tags,
+            ext,
+            category: category.name.clone(),
+            parent,
+        })
+    }
+
+    // `None` when the file has no such date or tag
+    fn get(&self, name: &str) -> Option<String> {
+        match name {
+            "year" => self.date.map(|date| date.year().to_string()),
+            "month" => self.date.map(|date| date.month().to_string()),
+            "day" => self.date.map(|date| date.day().to_string()),
+            "ext" => Some(self.ext.clone()),
+            "category" => Some(self.category.clone()),
+            "parent" => Some(self.parent.clone()),
+            "artist" => self.tags.artist.as_deref().and_then(sanitize),
+            "album" => self.tags.album.as_deref().and_then(sanitize),
+            "title" => self.tags.title.as_deref().and_then(sanitize),
+            "track" => self.tags.track.map(|track| track.to_string()),
+            _ => unreachable!("placeholders are checked when the config is loaded"),
+        }
+    }
+}
+
+// Photos are dated by when they were taken, everything else by the file's time
+fn file_date(path: &Path, options: &Options) -> io::Result<Option<NaiveDateTime>> {
+    if is_photo(path)? {
+        if let Some(taken) = capture_date(path) {
+            return Ok(Some(taken));
+        }
+        if !options.exif_fallback {
+            return Ok(None);
+        }
+    }
+
+    let time = file_time(&fs::metadata(path)?, options.date_from)?;
+    Ok(Some(DateTime::<Local>::from(time).naive_local()))
+}
+
+/// The time of a file `source` asks for, falling back to the modification
+/// time where the filesystem does not record creation times
+pub(crate) fn file_time(metadata: &fs::Metadata, source: DateSource) -> io::Result<SystemTime> {
+    match source {
+        DateSource::Created => metadata.created().or_else(|_| metadata.modified()),
+        DateSource::Modified => metadata.modified(),
+    }
+}
+
+// Make a tag usable as a single path component: no separators or characters
+// other systems reject, no leading dot, no trailing dots or spaces, and not
+// too long. `None` if nothing is left.
+fn sanitize(value: &str) -> Option<String> {
+    let mut clean: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    if clean.len() > MAX_TAG_LEN {
+        let end = (0..=MAX_TAG_LEN).rev().find(|&i| clean.is_char_boundary(i)).unwrap_or(0);
+        clean.truncate(end);
+    }
+
+    let clean = clean.trim().trim_end_matches(['.', ' ']);
+    let clean = match clean.strip_prefix('.') {
+        Some(rest) => format!("_{}", rest),
+        None => clean.to_string(),
+    };
+
+    (!clean.is_empty()).then_some(clean)
+}
+
+// What a directory placeholder becomes for a file without that tag
+fn unknown(name: &str) -> String {
+    match name {
+        "artist" => "Unknown Artist".to_string(),
+        "album" => "Unknown Album".to_string(),
+        "title" => "Unknown Title".to_string(),
+        _ => "Unknown".to_string(),
+    }
+}
+
+/// Check the placeholders of a destination, returning a message for the first bad one
+pub(crate) fn check_template(template: &str) -> Result<(), String> {
+    render(template, |name| {
+        if PLACEHOLDERS.contains(&name) {
+            Ok(String::new())
+        } else {
+            Err(format!("unknown placeholder '{{{}}}'", name))
+        }
+    })
+    .map(|_| ())
+}
+
+/// Expand the placeholders of a destination for one file. For a file without
+/// a date, the first directory naming a date part becomes `undated` and any
+/// further ones are left out, e.g. `~/Pictures/{year}/{month}` gives
+/// `~/Pictures/Undated`. Missing tags become e.g. `Unknown Artist`.
+pub(crate) fn expand_template(template: &Path, values: &TemplateValues, undated: &str) -> PathBuf {
+    let mut undated_done = false;
+
+    template
+        .components()
+        .filter_map(|component| match component.as_os_str().to_str() {
+            Some(text) if values.date.is_none() && uses_any(text, &DATE_PLACEHOLDERS) => {
+                let first = !undated_done;
+                undated_done = true;
+                first.then(|| PathBuf::from(undated))
+            }
+            Some(text) if is_templated(component) => {
+                let expanded = render(text, |name| Ok(values.get(name).unwrap_or_else(|| unknown(name))))
+                    .expect("templates are checked when the config is loaded");
+                Some(PathBuf::from(expanded))
+            }
+            _ => Some(PathBuf::from(component.as_os_str())),
+        })
+        .collect()
+}
+
+/// Expand a category's `rename` template into a new name for `source`,
+/// keeping its extension. The name stays as it is when the file lacks one
+/// of the tags or dates the template uses.
+pub(crate) fn expand_name(template: &str, values: &TemplateValues, source: &Path) -> OsString {
+    let original = source.file_name().unwrap_or_default().to_os_string();
+
+    let Ok(stem) = render(template, |name| values.get(name).ok_or_else(String::new)) else {
+        return original;
+    };
+    if stem.trim().is_empty() {
+        return original;
+    }
+
+    let mut name = OsString::from(stem);
+    if let Some(ext) = source.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    name
+}
+
+/// The part of a destination before its first placeholder, which is the same
+/// for every file
+pub(crate) fn template_root(template: &Path) -> PathBuf {
+    template.components().take_while(|c| !is_templated(*c)).collect()
+}
+
+// Whether the template uses any of the given placeholders
+fn uses_any(template: &str, names: &[&str]) -> bool {
+    let mut found = false;
+    let _ = render(template, |name| {
+        found |= names.contains(&name);
+        Ok(String::new())
+    });
+    found
+}
+
+fn is_templated(component: Component) -> bool {
+    let text = component.as_os_str().to_string_lossy();
+    text.contains('{') || text.contains('}')
+}
+
+// Replace every `{name}` or `{name:0N}` with the value from `lookup`, padded
+// with zeros to N digits. `{{` and `}}` stand for literal braces.
+fn render(template: &str, mut lookup: impl FnMut(&str) -> Result<String, String>) -> Result<String, String> {
+    let mut rendered = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '}' && chars.peek() == Some(&'}') {
+            chars.next();
+            rendered.push('}');
+            continue;
+        }
+        if c != '{' {
+            rendered.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'{') {
+            chars.next();
+            rendered.push('{');
+            continue;
+        }
+
+        let mut placeholder = String::new();
+        loop {
+            match chars.next() {
+                Some('}') => break,
+                Some(c) => placeholder.push(c),
+                None => return Err(format!("unclosed placeholder '{{{}'", placeholder)),
+            }
+        }
+
+        let (name, width) = match placeholder.split_once(':') {
+            None => (placeholder.as_str(), 0),
+            Some((name, spec)) => {
+                let width = spec
+                    .strip_prefix('0')
+                    .and_then(|digits| digits.parse().ok())
+                    .filter(|_| NUMERIC.contains(&name))
+                    .ok_or_else(|| format!("invalid format '{}' in placeholder '{{{}}}'", spec, placeholder))?;
+                (name, width)
+            }
+        };
+
+        let value = lookup(name)?;
+        rendered.push_str(&format!("{:0>width$}", value, width = width));
+    }
+
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn lookup(name: &str) -> Result<String, String> {
+        match name {
+            "year" => Ok("2024".to_string()),
+            "month" => Ok("3".to_string()),
+            "artist" => Ok("Nina Simone".to_string()),
+            _ => Err(format!("no {}", name)),
+        }
+    }
+
+    #[test]
+    fn placeholders_are_replaced_and_padded() {
+        assert_eq!(render("{year}/{month:02}", lookup).unwrap(), "2024/03");
+        assert_eq!(render("{artist} - {{live}}", lookup).unwrap(), "Nina Simone - {live}");
+        assert_eq!(render("plain", lookup).unwrap(), "plain");
+        assert_eq!(render("{title}", lookup).unwrap_err(), "no title");
+        assert!(render("{year", lookup).unwrap_err().contains("unclosed"));
+    }
+
+    #[test]
+    fn templates_are_checked_without_a_file() {
+        assert!(check_template("~/Pictures/{year}/{month:02}").is_ok());
+        assert!(check_template("{track:03} - {title}").is_ok());
+        assert!(check_template("{{not a placeholder}}").is_ok());
+        assert_eq!(check_template("{colour}").unwrap_err(), "unknown placeholder '{colour}'");
+        assert!(check_template("{artist:02}").unwrap_err().contains("invalid format"));
+        assert!(check_template("{month:2}").unwrap_err().contains("invalid format"));
+    }
+
+    #[test]
+    fn tags_are_made_safe_as_one_path_component() {
+        assert_eq!(sanitize("AC/DC").as_deref(), Some("AC_DC"));
+        assert_eq!(sanitize("a\\b:c*d?e\"f<g>h|i\tj").as_deref(), Some("a_b_c_d_e_f_g_h_i_j"));
+        assert_eq!(sanitize(".hidden").as_deref(), Some("_hidden"));
+        assert_eq!(sanitize("  Live...  ").as_deref(), Some("Live"));
+        assert_eq!(sanitize("..").as_deref(), None);
+        assert_eq!(sanitize(" . ").as_deref(), None);
+        assert_eq!(sanitize("").as_deref(), None);
+
+        // Cut at a char boundary rather than in the middle of a character
+        let long = format!("a{}", "é".repeat(MAX_TAG_LEN));
+        let clean = sanitize(&long).unwrap();
+        assert_eq!(clean.len(), MAX_TAG_LEN - 1);
+        assert!(clean.ends_with('é'));
+    }
+
+    #[test]
+    fn files_without_tags_go_to_unknown_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("song.mp3");
+        fs::write(&path, "not really audio").unwrap();
+        let category = Category {
+            name: "music".to_string(),
+            extensions: vec!["mp3".to_string()],
+            destination: PathBuf::from("/music/{artist}/{album}"),
+            rename: Some("{track:02} - {title}".to_string()),
+            mode: None,
+        };
+
+        let values = TemplateValues::for_file(&path, &category, &Options::default()).unwrap();
+        assert_eq!(
+            expand_template(&category.destination, &values, "Undated"),
+            Path::new("/music/Unknown Artist/Unknown Album")
+        );
+        assert_eq!(expand_name(category.rename.as_deref().unwrap(), &values, &path), "song.mp3");
+    }
+
+    fn dated_category(destination: &str) -> Category {
+        Category {
+            name: "pictures".to_string(),
+            extensions: vec!["jpg".to_string()],
+            destination: PathBuf::from(destination),
+            rename: None,
+            mode: None,
+        }
+    }
+
+    // A file last modified on 14 March 2021
+    fn file(dir: &Path, name: &str, content: &[u8]) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        let modified = Local.with_ymd_and_hms(2021, 3, 14, 12, 0, 0).unwrap();
+        fs::File::options().write(true).open(&path).unwrap().set_modified(modified.into()).unwrap();
+        path
+    }
+
+    #[test]
+    fn photos_without_a_capture_date_fall_back_to_the_file_time_unless_told_not_to() {
+        let dir = tempfile::tempdir().unwrap();
+        let photo = file(dir.path(), "IMG_0001.jpg", b"\xFF\xD8\xFF\xE0\x00\x10JFIF\x00");
+        let note = file(dir.path(), "note.jpg", b"not a photo at all");
+        let category = dated_category("/pictures/{year}/{month:02}/{day:02}");
+
+        let options = Options::default();
+        let values = TemplateValues::for_file(&photo, &category, &options).unwrap();
+        assert_eq!(expand_template(&category.destination, &values, "Undated"), Path::new("/pictures/2021/03/14"));
+
+        // Only the first date directory becomes the undated one
+        let options = Options { exif_fallback: false, undated: "No date".to_string(), ..Options::default() };
+        let values = TemplateValues::for_file(&photo, &category, &options).unwrap();
+        assert_eq!(expand_template(&category.destination, &values, &options.undated), Path::new("/pictures/No date"));
+
+        // Files that are not photos always have their file time
+        let values = TemplateValues::for_file(&note, &category, &options).unwrap();
+        assert_eq!(expand_template(&category.destination, &values, "Undated"), Path::new("/pictures/2021/03/14"));
+    }
+
+    #[test]
+    fn the_undated_directory_keeps_the_rest_of_the_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let photo = file(dir.path(), "IMG_0001.jpg", b"\xFF\xD8\xFF\xE0\x00\x10JFIF\x00");
+        let category = dated_category("/pictures/{category}/{year}-{month:02}/{day}/{ext}");
+        let options = Options { exif_fallback: false, ..Options::default() };
+
+        let values = TemplateValues::for_file(&photo, &category, &options).unwrap();
+        assert_eq!(
+            expand_template(&category.destination, &values, "Undated"),
+            Path::new("/pictures/pictures/Undated/jpg")
+        );
+    }
+
+    #[test]
+    fn the_root_is_the_part_before_any_placeholder() {
+        assert_eq!(template_root(Path::new("/music/{artist}/{album}")), Path::new("/music"));
+        assert_eq!(template_root(Path::new("/docs")), Path::new("/docs"));
+        assert_eq!(template_root(Path::new("{year}")), Path::new(""));
+    }
+}
@@ -0,0 +1,280 @@
+use crate::dupes::{is_identical, is_same_file};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs::{self, File, FileTimes, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Suffix of the temporary file a cross-filesystem copy is written to
+const PARTIAL_SUFFIX: &str = ".kondo-partial";
+// Suffix of the temporary hard link replacing a duplicate
+const LINK_SUFFIX: &str = ".kondo-link";
+
+/// How a file is brought to its destination
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferMode {
+    /// Move the file, leaving nothing behind
+    #[default]
+    Move,
+    /// Copy the file, leaving the source untouched
+    Copy,
+    /// Create a symbolic link to the source
+    Symlink,
+    /// Create a hard link to the source, which must be on the same filesystem
+    Hardlink,
+}
+
+impl TransferMode {
+    pub const NAMES: [&'static str; 4] = ["move", "copy", "symlink", "hardlink"];
+
+    /// e.g. `copy`, for reporting what would be done
+    pub fn verb(self) -> &'static str {
+        match self {
+            TransferMode::Move => "move",
+            TransferMode::Copy => "copy",
+            TransferMode::Symlink => "symlink",
+            TransferMode::Hardlink => "hardlink",
+        }
+    }
+
+    /// e.g. `copied`, for reporting what was done
+    pub fn past_tense(self) -> &'static str {
+        match self {
+            TransferMode::Move => "moved",
+            TransferMode::Copy => "copied",
+            TransferMode::Symlink => "symlinked",
+            TransferMode::Hardlink => "hardlinked",
+        }
+    }
+}
+
+impl FromStr for TransferMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TransferMode, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "move" => Ok(TransferMode::Move),
+            "copy" => Ok(TransferMode::Copy),
+            "symlink" => Ok(TransferMode::Symlink),
+            "hardlink" => Ok(TransferMode::Hardlink),
+            _ => Err(format!("unknown transfer mode '{}'", s)),
+        }
+    }
+}
+
+/// Bring `source` to `dest_path` as `mode` says, creating the destination
+/// directory and replacing any file already there. Copies and links are made
+/// under a temporary name and renamed into place, so `dest_path` is never
+/// left half-written.
+pub(crate) fn transfer(source: &Path, dest_path: &Path, mode: TransferMode) -> io::Result<()> {
+    if mode == TransferMode::Move {
+        return move_file(source, dest_path);
+    }
+
+    if let Some(destination) = dest_path.parent() {
+        fs::create_dir_all(destination)?;
+    }
+
+    let partial = temp_path(dest_path, PARTIAL_SUFFIX);
+    let _ = fs::remove_file(&partial);
+
+    let result = match mode {
+        TransferMode::Copy => copy_verified(source, &partial),
+        // Relative links would break as soon as the destination is elsewhere
+        TransferMode::Symlink => symlink(&std::path::absolute(source)?, &partial),
+        TransferMode::Hardlink => fs::hard_link(source, &partial),
+        TransferMode::Move => unreachable!("moves are handled above"),
+    };
+
+    if let Err(e) = result.and_then(|()| fs::rename(&partial, dest_path)) {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Whether `dest_path` already is what transferring `source` with `mode` would
+/// make of it, e.g. an identical copy, so that running again changes nothing.
+/// Never true for moves, which leave no source behind.
+pub(crate) fn is_transferred(source: &Path, dest_path: &Path, mode: TransferMode) -> bool {
+    match mode {
+        TransferMode::Move => false,
+        TransferMode::Copy => is_identical(source, dest_path).unwrap_or(false),
+        TransferMode::Symlink => is_link_to(dest_path, source),
+        TransferMode::Hardlink => is_same_file(source, dest_path).unwrap_or(false),
+    }
+}
+
+/// Whether `link` is a symbolic link to `target`
+pub(crate) fn is_link_to(link: &Path, target: &Path) -> bool {
+    match (fs::read_link(link), std::path::absolute(target)) {
+        (Ok(points_to), Ok(target)) => points_to == target,
+        _ => false,
+    }
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+/// Move `source` to `dest_path`, creating the destination directory.
+///
+/// Within one filesystem this is a plain rename. Across filesystems the file is
+/// copied under a temporary name next to its destination, synced and checked
+/// against the source, and only then renamed into place and the source removed.
+/// An interrupted transfer leaves at most a hidden `.kondo-partial` file behind,
+/// never a truncated file under the final name.
+pub(crate) fn move_file(source: &Path, dest_path: &Path) -> io::Result<()> {
+    // Create destination directory if it doesn't exist
+    if let Some(destination) = dest_path.parent() {
+        fs::create_dir_all(destination)?;
+    }
+
+    match fs::rename(source, dest_path) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => move_across_devices(source, dest_path),
+        result => result,
+    }
+}
+
+fn move_across_devices(source: &Path, dest_path: &Path) -> io::Result<()> {
+    let partial = temp_path(dest_path, PARTIAL_SUFFIX);
+
+    // A leftover from an earlier interrupted transfer is never a complete file
+    let _ = fs::remove_file(&partial);
+
+    let result = copy_verified(source, &partial).and_then(|()| fs::rename(&partial, dest_path));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+
+    // Make the rename durable before the only other copy goes away
+    if let Some(destination) = dest_path.parent() {
+        File::open(destination)?.sync_all()?;
+    }
+
+    fs::remove_file(source).map_err(|e| {
+        io::Error::new(e.kind(), format!("copied to {}, but could not remove the source: {}", dest_path.display(), e))
+    })
+}
+
+/// Replace `path` with a hard link to `target`. The link is made under a
+/// temporary name and renamed over `path`, so `path` never goes missing.
+pub(crate) fn replace_with_hardlink(path: &Path, target: &Path) -> io::Result<()> {
+    let link = temp_path(path, LINK_SUFFIX);
+
+    let _ = fs::remove_file(&link);
+    fs::hard_link(target, &link)?;
+    fs::rename(&link, path).inspect_err(|_| {
+        let _ = fs::remove_file(&link);
+    })
+}
+
+// Copy `source` to a new file at `partial` with the same permissions and
+// times, then read the copy back and compare it to the source
+fn copy_verified(source: &Path, partial: &Path) -> io::Result<()> {
+    let mut input = File::open(source)?;
+    let metadata = input.metadata()?;
+
+    let mut output = OpenOptions::new().write(true).create_new(true).open(partial)?;
+    let source_hash = copy_hashing(&mut input, &mut output)?;
+
+    output.set_times(FileTimes::new().set_accessed(metadata.accessed()?).set_modified(metadata.modified()?))?;
+    output.set_permissions(metadata.permissions())?;
+    output.sync_all()?;
+    drop(output);
+
+    let copied_len = fs::metadata(partial)?.len();
+    let copied_hash = copy_hashing(&mut File::open(partial)?, &mut io::sink())?;
+    if copied_len != metadata.len() || copied_hash != source_hash {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the copy does not match the source"));
+    }
+
+    Ok(())
+}
+
+// Copy everything from `input` to `output`, returning the BLAKE3 hash of the data
+fn copy_hashing(input: &mut impl Read, output: &mut impl Write) -> io::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+
+    loop {
+        let read = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buffer[..read]);
+        output.write_all(&buffer[..read])?;
+    }
+
+    Ok(hasher.finalize())
+}
+
+// A hidden name next to `path`, e.g. `dir/.name.kondo-partial` for `dir/name`
+fn temp_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_move_across_devices_copies_then_removes_the_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("a.txt");
+        let dest_path = dir.path().join("dest/a.txt");
+        fs::write(&source, "content").unwrap();
+        fs::create_dir(dir.path().join("dest")).unwrap();
+        // Left over from an interrupted transfer
+        fs::write(temp_path(&dest_path, PARTIAL_SUFFIX), "cont").unwrap();
+
+        move_across_devices(&source, &dest_path).unwrap();
+
+        assert_eq!(fs::read_to_string(&dest_path).unwrap(), "content");
+        assert!(!source.exists());
+        assert!(!temp_path(&dest_path, PARTIAL_SUFFIX).exists());
+    }
+
+    #[test]
+    fn a_failed_move_across_devices_keeps_the_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("a.txt");
+        fs::write(&source, "content").unwrap();
+
+        // The destination directory is missing
+        let dest_path = dir.path().join("missing/a.txt");
+        assert!(move_across_devices(&source, &dest_path).is_err());
+        assert!(source.exists());
+        assert!(!dest_path.exists());
+    }
+
+    #[test]
+    fn copies_and_links_count_as_transferred_once_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("a.txt");
+        fs::write(&source, "content").unwrap();
+
+        for mode in [TransferMode::Copy, TransferMode::Symlink, TransferMode::Hardlink] {
+            let dest_path = dir.path().join(mode.verb()).join("a.txt");
+            assert!(!is_transferred(&source, &dest_path, mode));
+            transfer(&source, &dest_path, mode).unwrap();
+            assert!(is_transferred(&source, &dest_path, mode), "{:?}", mode);
+            assert_eq!(fs::read_to_string(&dest_path).unwrap(), "content");
+        }
+        assert!(source.exists());
+    }
+}
@@ -0,0 +1,518 @@
+use crate::config::{Category, Config};
+use crate::plan::{Action, Chosen, Planner};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Clear, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A file listed by `kondo tui`, with what the user made of it
+#[derive(Clone, Debug)]
+pub struct Entry<'a> {
+    pub source: PathBuf,
+    /// `None` when the file matches no category, in which case it stays put
+    pub category: Option<&'a Category>,
+    /// Left where it is, whatever its category
+    pub excluded: bool,
+    /// Name given by the user, instead of the one from the category
+    pub file_name: Option<OsString>,
+}
+
+impl<'a> Entry<'a> {
+    pub fn new(source: PathBuf, category: Option<&'a Category>) -> Entry<'a> {
+        Entry {
+            source,
+            category,
+            excluded: false,
+            file_name: None,
+        }
+    }
+
+    /// The file to plan, if it is to be organized at all
+    pub fn chosen(&self) -> Option<Chosen<'a>> {
+        let category = self.category.filter(|_| !self.excluded)?;
+        Some(Chosen {
+            source: self.source.clone(),
+            category,
+            file_name: self.file_name.clone(),
+        })
+    }
+}
+
+/// Show the files of a scan in the terminal, grouped by category, and let
+/// the user re-categorise, exclude and rename them and preview where they
+/// will go. Returns the files to organize, in the order of `entries`, or
+/// `None` if the user quit without going ahead.
+pub fn review<'a>(
+    config: &'a Config,
+    source_dir: &'a Path,
+    entries: Vec<Entry<'a>>,
+) -> io::Result<Option<Vec<Chosen<'a>>>> {
+    let mut terminal = ratatui::init();
+    let result = Review::new(config, source_dir, entries).run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+// A line of the file list
+#[derive(Clone, Copy)]
+enum Row {
+    /// The heading of a group, by index into the targets
+    Header(usize),
+    /// A file, by index into the entries
+    File(usize),
+}
+
+enum Mode {
+    Browse,
+    /// Picking a new category for the selected file
+    Pick(ListState),
+    /// Typing a new name for the selected file
+    Rename(String),
+    Preview { lines: Vec<Line<'static>>, scroll: usize },
+    /// Asking whether to go ahead with this many files
+    Confirm(usize),
+}
+
+enum Step {
+    Continue,
+    Quit,
+    Execute,
+}
+
+struct Review<'a> {
+    config: &'a Config,
+    source_dir: &'a Path,
+    // Every category a file can be put in, in the order of the config. Files
+    // matching none are grouped last.
+    targets: Vec<&'a Category>,
+    entries: Vec<Entry<'a>>,
+    rows: Vec<Row>,
+    // Index into the rows, always a file unless there are none
+    selected: usize,
+    // First row shown, and how many fit
+    offset: usize,
+    height: usize,
+    mode: Mode,
+    message: Option<String>,
+}
+
+impl<'a> Review<'a> {
+    fn new(config: &'a Config, source_dir: &'a Path, entries: Vec<Entry<'a>>) -> Review<'a> {
+        let mut review = Review {
+            config,
+            source_dir,
+            targets: config.targets().collect(),
+            entries,
+            rows: Vec::new(),
+            selected: 0,
+            offset: 0,
+            height: 1,
+            mode: Mode::Browse,
+            message: None,
+        };
+        review.group(None);
+        review
+    }
+
+    fn run(mut self, terminal: &mut DefaultTerminal) -> io::Result<Option<Vec<Chosen<'a>>>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+                return Ok(None);
+            }
+
+            self.message = None;
+            match self.handle(key) {
+                Step::Continue => {}
+                Step::Quit => return Ok(None),
+                Step::Execute => return Ok(Some(self.chosen())),
+            }
+        }
+    }
+
+    fn chosen(&self) -> Vec<Chosen<'a>> {
+        self.entries.iter().filter_map(Entry::chosen).collect()
+    }
+
+    // The group of a file: its category among the targets, or the last one
+    fn group_of(&self, entry: &Entry) -> usize {
+        entry
+            .category
+            .and_then(|category| self.targets.iter().position(|target| std::ptr::eq(*target, category)))
+            .unwrap_or(self.targets.len())
+    }
+
+    // Lay out the rows again, keeping `keep` selected if given
+    fn group(&mut self, keep: Option<usize>) {
+        let mut order: Vec<(usize, usize)> =
+            self.entries.iter().enumerate().map(|(index, entry)| (self.group_of(entry), index)).collect();
+        // Stable, so files keep the order of the scan within a group
+        order.sort_by_key(|(group, _)| *group);
+
+        self.rows.clear();
+        for (group, index) in order {
+            if !matches!(self.rows.last(), Some(Row::File(last)) if self.group_of(&self.entries[*last]) == group) {
+                self.rows.push(Row::Header(group));
+            }
+            self.rows.push(Row::File(index));
+        }
+
+        let selected = keep.and_then(|keep| self.rows.iter().position(|row| matches!(row, Row::File(i) if *i == keep)));
+        self.selected = selected.or_else(|| self.rows.iter().position(|row| matches!(row, Row::File(_)))).unwrap_or(0);
+    }
+
+    fn selected_entry(&self) -> Option<usize> {
+        match self.rows.get(self.selected) {
+            Some(Row::File(index)) => Some(*index),
+            _ => None,
+        }
+    }
+
+    // Move the selection by `by` files, stopping at either end
+    fn move_selection(&mut self, by: isize) {
+        let files: Vec<usize> =
+            (0..self.rows.len()).filter(|row| matches!(self.rows[*row], Row::File(_))).collect();
+        let Some(current) = files.iter().position(|row| *row == self.selected) else {
+            return;
+        };
+        let target = current.saturating_add_signed(by).min(files.len() - 1);
+        self.selected = files[target];
+    }
+
+    fn handle(&mut self, key: KeyEvent) -> Step {
+        match &mut self.mode {
+            Mode::Browse => return self.browse(key),
+            Mode::Pick(state) => match key.code {
+                KeyCode::Up | KeyCode::Char('k') => state.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => state.select_next(),
+                KeyCode::Enter => {
+                    let picked = state.selected().and_then(|i| self.targets.get(i).copied());
+                    if let (Some(category), Some(index)) = (picked, self.selected_entry()) {
+                        self.entries[index].category = Some(category);
+                        self.entries[index].excluded = false;
+                        self.group(Some(index));
+                    }
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Esc | KeyCode::Char('q') => self.mode = Mode::Browse,
+                _ => {}
+            },
+            Mode::Rename(name) => match key.code {
+                KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => name.clear(),
+                KeyCode::Char(c) => name.push(c),
+                KeyCode::Backspace => {
+                    name.pop();
+                }
+                KeyCode::Enter => {
+                    let name = name.trim().to_string();
+                    if name.contains(std::path::is_separator) || name == "." || name == ".." {
+                        self.message = Some(format!("'{}' is not a file name", name));
+                        return Step::Continue;
+                    }
+                    if let Some(index) = self.selected_entry() {
+                        // An empty name goes back to the one from the category
+                        self.entries[index].file_name = (!name.is_empty()).then(|| OsString::from(name));
+                    }
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Esc => self.mode = Mode::Browse,
+                _ => {}
+            },
+            Mode::Preview { lines, scroll } => {
+                let last = lines.len().saturating_sub(self.height);
+                match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => *scroll = scroll.saturating_sub(1),
+                    KeyCode::Down | KeyCode::Char('j') => *scroll = (*scroll + 1).min(last),
+                    KeyCode::PageUp => *scroll = scroll.saturating_sub(self.height),
+                    KeyCode::PageDown => *scroll = (*scroll + self.height).min(last),
+                    KeyCode::Home => *scroll = 0,
+                    KeyCode::End => *scroll = last,
+                    KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('p') => self.mode = Mode::Browse,
+                    KeyCode::Char('e') => return self.confirm(),
+                    _ => {}
+                }
+            }
+            Mode::Confirm(_) => match key.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => return Step::Execute,
+                _ => self.mode = Mode::Browse,
+            },
+        }
+        Step::Continue
+    }
+
+    fn browse(&mut self, key: KeyEvent) -> Step {
+        let page = self.height.max(1) as isize;
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::PageUp => self.move_selection(-page),
+            KeyCode::PageDown => self.move_selection(page),
+            KeyCode::Home => self.move_selection(isize::MIN),
+            KeyCode::End => self.move_selection(isize::MAX),
+            KeyCode::Char('c') => {
+                if let Some(index) = self.selected_entry() {
+                    let current = self.group_of(&self.entries[index]);
+                    let mut state = ListState::default();
+                    state.select(Some(current.min(self.targets.len().saturating_sub(1))));
+                    if self.targets.is_empty() {
+                        self.message = Some("The config has no categories".to_string());
+                    } else {
+                        self.mode = Mode::Pick(state);
+                    }
+                }
+            }
+            KeyCode::Char('x') | KeyCode::Char(' ') => {
+                if let Some(index) = self.selected_entry() {
+                    let entry = &mut self.entries[index];
+                    entry.excluded = !entry.excluded;
+                    self.move_selection(1);
+                }
+            }
+            KeyCode::Char('r') => {
+                if let Some(index) = self.selected_entry() {
+                    let entry = &self.entries[index];
+                    if entry.category.is_none() {
+                        self.message = Some("Pick a category first, with c".to_string());
+                    } else {
+                        let name = entry.file_name.as_deref().or(entry.source.file_name()).unwrap_or_default();
+                        self.mode = Mode::Rename(name.to_string_lossy().into_owned());
+                    }
+                }
+            }
+            KeyCode::Char('p') => {
+                self.mode = Mode::Preview {
+                    lines: self.preview(),
+                    scroll: 0,
+                }
+            }
+            KeyCode::Char('e') => return self.confirm(),
+            KeyCode::Char('q') | KeyCode::Esc => return Step::Quit,
+            _ => {}
+        }
+        Step::Continue
+    }
+
+    fn confirm(&mut self) -> Step {
+        let count = self.entries.iter().filter(|entry| entry.chosen().is_some()).count();
+        if count == 0 {
+            self.mode = Mode::Browse;
+            self.message = Some("No files to organize".to_string());
+        } else {
+            self.mode = Mode::Confirm(count);
+        }
+        Step::Continue
+    }
+
+    // The destination tree: every directory files would go to, with the files
+    // under it, then the files that would be left alone or fail
+    fn preview(&self) -> Vec<Line<'static>> {
+        let mut planner = Planner::new(&self.config.options);
+        let planned = planner.plan_chosen(self.chosen());
+
+        let mut dirs: BTreeMap<PathBuf, Vec<Line>> = BTreeMap::new();
+        let mut duplicates = Vec::new();
+        let mut skipped = Vec::new();
+        let mut failed = Vec::new();
+        for planned in planned {
+            let planned = match planned {
+                Ok(planned) => planned,
+                Err(e) => {
+                    failed.push(Line::from(format!("  {}", e)));
+                    continue;
+                }
+            };
+
+            let source = self.relative(&planned.source);
+            match &planned.action {
+                Action::Move | Action::Overwrite => {
+                    let dir = planned.destination.parent().unwrap_or(Path::new("")).to_path_buf();
+                    let name = planned.destination.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                    let mut spans = vec![Span::raw(format!("  {}", name)), dim(format!("  <- {}", source))];
+                    if planned.action == Action::Overwrite {
+                        spans.push(Span::styled(" (overwrites)", Style::new().fg(Color::Yellow)));
+                    } else if planned.duplicate_of.is_some() {
+                        spans.push(dim(" (duplicate)".to_string()));
+                    }
+                    dirs.entry(dir).or_default().push(Line::from(spans));
+                }
+                Action::Delete | Action::Hardlink => {
+                    let verb = if planned.action == Action::Delete { "deleted" } else { "linked" };
+                    let line = format!("  {} ({}, same as {})", source, verb, planned.destination.display());
+                    duplicates.push(Line::from(line));
+                }
+                Action::Skip(reason) => skipped.push(Line::from(format!("  {} ({})", source, reason))),
+            }
+        }
+
+        let mut lines = Vec::new();
+        let count: usize = dirs.values().map(Vec::len).sum();
+        lines.push(Line::from(format!("{} file(s) in {} director(ies)", count, dirs.len())).bold());
+        for (dir, files) in dirs {
+            lines.push(Line::default());
+            let mut heading = vec![Span::styled(format!("{}/", dir.display()), Style::new().fg(Color::Blue).bold())];
+            if !dir.exists() {
+                heading.push(dim(" (new)".to_string()));
+            }
+            lines.push(Line::from(heading));
+            lines.extend(files);
+        }
+        for (title, section) in [("Duplicates", duplicates), ("Skipped", skipped), ("Failed", failed)] {
+            if !section.is_empty() {
+                lines.push(Line::default());
+                lines.push(Line::from(format!("{} ({})", title, section.len())).bold());
+                lines.extend(section);
+            }
+        }
+        lines
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        path.strip_prefix(self.source_dir).unwrap_or(path).display().to_string()
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [title, body, status] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        self.height = body.height as usize;
+
+        let (total, count) = (self.entries.len(), self.entries.iter().filter(|e| e.chosen().is_some()).count());
+        let heading = format!(" kondo: {} ({} file(s), {} to organize)", self.source_dir.display(), total, count);
+        frame.render_widget(Paragraph::new(heading).style(Style::new().add_modifier(Modifier::REVERSED)), title);
+
+        match &self.mode {
+            Mode::Preview { lines, scroll } => {
+                let shown: Vec<Line> = lines.iter().skip(*scroll).take(self.height).cloned().collect();
+                frame.render_widget(Paragraph::new(shown), body);
+            }
+            _ => self.draw_files(frame, body),
+        }
+
+        let help = match &self.mode {
+            Mode::Browse => "↑↓ select  c category  x exclude  r rename  p preview  e execute  q quit",
+            Mode::Pick(_) => "↑↓ select  enter pick  esc cancel",
+            Mode::Rename(_) => "enter keep  esc cancel  (an empty name goes back to the category's)",
+            Mode::Preview { .. } => "↑↓ scroll  p back  e execute  q back",
+            Mode::Confirm(_) => "y go ahead  any other key to go back",
+        };
+        let status_line = match &self.message {
+            Some(message) => Line::from(message.as_str()).style(Style::new().fg(Color::Yellow)),
+            None => Line::from(help).style(Style::new().add_modifier(Modifier::DIM)),
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+
+        match &mut self.mode {
+            Mode::Pick(state) => {
+                let items: Vec<ListItem> = self
+                    .targets
+                    .iter()
+                    .map(|category| ListItem::new(format!("{}  ({})", category.name, category.destination.display())))
+                    .collect();
+                let area = popup(frame.area(), items.len() as u16 + 2);
+                let list = List::new(items)
+                    .block(Block::bordered().title(" Category "))
+                    .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+                frame.render_widget(Clear, area);
+                frame.render_stateful_widget(list, area, state);
+            }
+            Mode::Rename(name) => {
+                let area = popup(frame.area(), 3);
+                let input = Paragraph::new(format!("{}▏", name)).block(Block::bordered().title(" New name "));
+                frame.render_widget(Clear, area);
+                frame.render_widget(input, area);
+            }
+            Mode::Confirm(count) => {
+                let area = popup(frame.area(), 3);
+                let question = Paragraph::new(format!("Organize {} file(s)? [y/N]", count)).block(Block::bordered());
+                frame.render_widget(Clear, area);
+                frame.render_widget(question, area);
+            }
+            Mode::Browse | Mode::Preview { .. } => {}
+        }
+    }
+
+    fn draw_files(&mut self, frame: &mut Frame, area: Rect) {
+        if self.entries.is_empty() {
+            frame.render_widget(Paragraph::new("No files found"), area);
+            return;
+        }
+
+        // Scroll just enough to keep the selection in view, with its heading
+        // when there is room
+        if self.selected < self.offset + 1 {
+            self.offset = self.selected.saturating_sub(1);
+        } else if self.selected >= self.offset + self.height {
+            self.offset = self.selected + 1 - self.height;
+        }
+
+        let mut lines = Vec::new();
+        for (row, kind) in self.rows.iter().enumerate().skip(self.offset).take(self.height) {
+            let line = match *kind {
+                Row::Header(group) => self.header(group),
+                Row::File(index) => self.file_line(&self.entries[index]),
+            };
+            lines.push(if row == self.selected { line.patch_style(Modifier::REVERSED) } else { line });
+        }
+        frame.render_widget(Paragraph::new(lines), area);
+    }
+
+    fn header(&self, group: usize) -> Line<'static> {
+        let count = self.entries.iter().filter(|entry| self.group_of(entry) == group).count();
+        let style = Style::new().fg(Color::Blue).bold();
+        match self.targets.get(group) {
+            Some(category) => Line::from(vec![
+                Span::styled(format!("{} ({})", category.name, count), style),
+                dim(format!("  -> {}", category.destination.display())),
+            ]),
+            None => Line::from(vec![
+                Span::styled(format!("no category ({})", count), style),
+                dim("  left where they are".to_string()),
+            ]),
+        }
+    }
+
+    fn file_line(&self, entry: &Entry) -> Line<'static> {
+        let mut spans = vec![Span::raw(format!("  {}", self.relative(&entry.source)))];
+        if let Some(file_name) = &entry.file_name {
+            spans.push(Span::styled(format!(" -> {}", file_name.to_string_lossy()), Style::new().fg(Color::Cyan)));
+        }
+        if entry.excluded {
+            spans.push(dim(" (excluded)".to_string()));
+        }
+
+        let line = Line::from(spans);
+        if entry.excluded || entry.category.is_none() {
+            line.patch_style(Modifier::DIM)
+        } else {
+            line
+        }
+    }
+}
+
+fn dim(text: String) -> Span<'static> {
+    Span::styled(text, Style::new().add_modifier(Modifier::DIM))
+}
+
+// A box of `height` lines in the middle of `area`
+fn popup(area: Rect, height: u16) -> Rect {
+    let width = (area.width * 2 / 3).max(20).min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
@@ -0,0 +1,178 @@
+use crate::error::KondoError;
+use notify::event::{EventKind, ModifyKind};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often pending files are checked, and a lost source directory looked for
+const TICK: Duration = Duration::from_millis(250);
+
+pub struct WatchOptions {
+    /// Also watch subdirectories of the source
+    pub recursive: bool,
+    /// How long a file must go without changes before it is handed over
+    pub settle: Duration,
+}
+
+/// What [`watch`] reports while it runs
+#[derive(Debug)]
+pub enum WatchEvent {
+    /// Files that have stopped changing, ready to be organized
+    Ready(Vec<PathBuf>),
+    /// A problem reported by the watcher that does not stop it
+    Warning(String),
+    /// The source directory was removed, and is being waited for
+    SourceGone,
+    /// The source directory is back and watched again
+    SourceBack,
+}
+
+// A file seen by the watcher but maybe still being written
+struct Pending {
+    last_change: Instant,
+    size: Option<u64>,
+}
+
+/// Watch `source_dir` for files created in or moved into it, and hand batches
+/// of files to `on_event` once they have stopped changing. The paths handed
+/// over start with the canonical form of `source_dir`. Runs until `on_event`
+/// fails. When the source directory is removed, waits for it to come back and
+/// watches it again.
+pub fn watch(
+    source_dir: &Path,
+    options: &WatchOptions,
+    mut on_event: impl FnMut(WatchEvent) -> Result<(), KondoError>,
+) -> Result<(), KondoError> {
+    // Events carry canonical paths
+    let source_dir = &fs::canonicalize(source_dir).map_err(|e| KondoError::io(source_dir, e))?;
+
+    let mut pending: HashMap<PathBuf, Pending> = HashMap::new();
+
+    loop {
+        let (_watcher, events) = start_watcher(source_dir, options)?;
+
+        // Runs until the source directory goes away
+        loop {
+            match events.recv_timeout(TICK) {
+                Ok(Ok(event)) => {
+                    if event.paths.iter().any(|path| path == source_dir)
+                        && matches!(event.kind, EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)))
+                    {
+                        break;
+                    }
+
+                    if is_arrival(&event.kind) {
+                        for path in event.paths {
+                            let size = fs::metadata(&path).ok().map(|meta| meta.len());
+                            pending.insert(path, Pending { last_change: Instant::now(), size });
+                        }
+                    }
+                }
+                Ok(Err(e)) => on_event(WatchEvent::Warning(format!("watching {}: {}", source_dir.display(), e)))?,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            if !source_dir.is_dir() {
+                break;
+            }
+
+            let ready = settled_files(&mut pending, options.settle);
+            if !ready.is_empty() {
+                on_event(WatchEvent::Ready(ready))?;
+            }
+        }
+
+        on_event(WatchEvent::SourceGone)?;
+        while !source_dir.is_dir() {
+            thread::sleep(TICK);
+        }
+        on_event(WatchEvent::SourceBack)?;
+
+        // Files may have arrived between the directory coming back and the
+        // new watch starting, so treat whatever is there as new
+        pending.clear();
+        for path in existing_files(source_dir, options.recursive) {
+            let size = fs::metadata(&path).ok().map(|meta| meta.len());
+            pending.insert(path, Pending { last_change: Instant::now(), size });
+        }
+    }
+}
+
+fn existing_files(dir: &Path, recursive: bool) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return files;
+    };
+
+    for entry in entries.flatten() {
+        match entry.file_type() {
+            Ok(t) if t.is_dir() && recursive => files.extend(existing_files(&entry.path(), true)),
+            Ok(t) if t.is_file() => files.push(entry.path()),
+            _ => {}
+        }
+    }
+
+    files
+}
+
+type Events = Receiver<notify::Result<notify::Event>>;
+
+fn start_watcher(source_dir: &Path, options: &WatchOptions) -> Result<(RecommendedWatcher, Events), KondoError> {
+    let watch_error = |e: notify::Error| KondoError::io(source_dir, std::io::Error::other(e.to_string()));
+
+    let (sender, events) = channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(watch_error)?;
+    let mode = if options.recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    watcher.watch(source_dir, mode).map_err(watch_error)?;
+
+    Ok((watcher, events))
+}
+
+// Files being created, written to, or moved in
+fn is_arrival(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Name(_) | ModifyKind::Any)
+    )
+}
+
+// Take the pending files that have kept the same size for the settle time
+fn settled_files(pending: &mut HashMap<PathBuf, Pending>, settle: Duration) -> Vec<PathBuf> {
+    let mut ready = Vec::new();
+
+    pending.retain(|path, file| {
+        if file.last_change.elapsed() < settle {
+            return true;
+        }
+
+        let Ok(meta) = fs::metadata(path) else {
+            // Gone again, e.g. a temporary file or moved away
+            return false;
+        };
+        if !meta.is_file() {
+            return false;
+        }
+
+        if file.size == Some(meta.len()) {
+            ready.push(path.clone());
+            return false;
+        }
+
+        // Still growing, give it another round
+        file.size = Some(meta.len());
+        file.last_change = Instant::now();
+        true
+    });
+
+    ready.sort();
+    ready
+}